@@ -0,0 +1,401 @@
+//! Like pactl, but sees the server the same way pulsecontrol does.
+
+extern crate libpulse_binding as pulse;
+
+use futures::future::ready;
+use futures::stream::StreamExt;
+use pulse::volume::{ChannelVolumes, Volume};
+use pulsecontrol::{
+    facility_tag, set_volume_fraction, volume_fraction, Card, Client, ConnectionState, Event, ListKind, Module,
+    ModuleId, ObjectRef, PulseAddMessage, PulseCommand, PulseHandle, PulseMessage, RawModule, Sink, SinkId, SinkInput,
+    SinkInputId, Source, SourceId, SourceOutput, SourceOutputId,
+};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::process::exit;
+
+const USAGE: &str = "Usage: pulsectl [--server SERVER] [--json] COMMAND
+
+Commands:
+  list sinks|sources|sink-inputs|source-outputs
+  set-volume sink|source|sink-input|source-output TARGET PERCENT
+  mute sink|source|sink-input|source-output TARGET [on|off|toggle]
+  move sink-input|source-output TARGET SINK|SOURCE
+  set-default sink|source TARGET
+  load-module NAME [ARGUMENTS]
+  unload-module INDEX
+  snapshot
+  watch
+
+TARGET is an index or a name.
+
+  -s, --server SERVER   Pulse server to connect to, defaults to $PULSE_SERVER or the local server.
+  --json                Print lists as JSON, and events as one JSON object per line.
+  -h, --help            Show this message.";
+
+struct Args {
+    server: Option<String>,
+    json: bool,
+    command: Vec<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args { server: None, json: false, command: Vec::new() };
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "-s" | "--server" => match argv.next() {
+                Some(server) => args.server = Some(server),
+                None => return Err(format!("{} needs a value", arg)),
+            },
+            _ if arg.starts_with("--server=") => args.server = Some(arg["--server=".len()..].to_string()),
+            "--json" => args.json = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ => args.command.push(arg),
+        }
+    }
+    Ok(args)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Sink,
+    Source,
+    SinkInput,
+    SourceOutput,
+}
+
+impl Kind {
+    fn parse(kind: &str) -> Result<Kind, String> {
+        match kind {
+            "sink" | "sinks" => Ok(Kind::Sink),
+            "source" | "sources" => Ok(Kind::Source),
+            "sink-input" | "sink-inputs" => Ok(Kind::SinkInput),
+            "source-output" | "source-outputs" => Ok(Kind::SourceOutput),
+            _ => Err(format!("Unknown object kind {}", kind)),
+        }
+    }
+
+    fn list(self) -> ListKind {
+        match self {
+            Kind::Sink => ListKind::Sinks,
+            Kind::Source => ListKind::Sources,
+            Kind::SinkInput => ListKind::SinkInputs,
+            Kind::SourceOutput => ListKind::SourceOutputs,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Sink => "sink",
+            Kind::Source => "source",
+            Kind::SinkInput => "sink-input",
+            Kind::SourceOutput => "source-output",
+        }
+    }
+}
+
+/// What the commands need to know about a sink, source or stream.
+struct Object {
+    kind: Kind,
+    index: u32,
+    name: String,
+    description: Option<String>,
+    volume: ChannelVolumes,
+    mute: bool,
+    // The sink or source a stream is connected to.
+    device: Option<u32>,
+}
+
+impl Object {
+    fn from_message(msg: PulseAddMessage) -> Option<Object> {
+        let name = |name: &Option<Cow<str>>| name.as_ref().map(|n| n.to_string()).unwrap_or_default();
+        match msg {
+            PulseAddMessage::MsgSink(info) => Some(Object {
+                kind: Kind::Sink,
+                index: info.index,
+                name: name(&info.name),
+                description: info.description.as_ref().map(|d| d.to_string()),
+                volume: info.volume,
+                mute: info.mute,
+                device: None,
+            }),
+            PulseAddMessage::MsgSource(info) => Some(Object {
+                kind: Kind::Source,
+                index: info.index,
+                name: name(&info.name),
+                description: info.description.as_ref().map(|d| d.to_string()),
+                volume: info.volume,
+                mute: info.mute,
+                device: None,
+            }),
+            PulseAddMessage::MsgSinkInput(info) => Some(Object {
+                kind: Kind::SinkInput,
+                index: info.index,
+                name: name(&info.name),
+                description: None,
+                volume: info.volume,
+                mute: info.mute,
+                device: Some(info.sink),
+            }),
+            PulseAddMessage::MsgSourceOutput(info) => Some(Object {
+                kind: Kind::SourceOutput,
+                index: info.index,
+                name: name(&info.name),
+                description: None,
+                volume: info.volume,
+                mute: info.mute,
+                device: Some(info.source),
+            }),
+            _ => None,
+        }
+    }
+
+    fn target(&self) -> ObjectRef {
+        match self.kind {
+            Kind::Sink => ObjectRef::Sink(SinkId(self.index)),
+            Kind::Source => ObjectRef::Source(SourceId(self.index)),
+            Kind::SinkInput => ObjectRef::SinkInput(SinkInputId(self.index)),
+            Kind::SourceOutput => ObjectRef::SourceOutput(SourceOutputId(self.index)),
+        }
+    }
+
+    fn percent(&self) -> u32 {
+        (volume_fraction(&self.volume) * 100.0).round() as u32
+    }
+
+    fn line(&self) -> String {
+        let mut line = format!("#{}\t{}\t{}%", self.index, self.name, self.percent());
+        if self.mute {
+            line.push_str("\tmuted");
+        }
+        if let Some(device) = self.device {
+            line.push_str(&format!("\ton #{}", device));
+        }
+        if let Some(description) = &self.description {
+            line.push_str(&format!("\t{}", description));
+        }
+        line
+    }
+}
+
+// The same model `watch --json` prints.
+fn json(msg: &PulseAddMessage) -> Option<Value> {
+    let value = match msg {
+        PulseAddMessage::MsgSink(info) => serde_json::to_value(Sink::from(info)),
+        PulseAddMessage::MsgSource(info) => serde_json::to_value(Source::from(info)),
+        PulseAddMessage::MsgSinkInput(info) => serde_json::to_value(SinkInput::from(info)),
+        PulseAddMessage::MsgSourceOutput(info) => serde_json::to_value(SourceOutput::from(info)),
+        _ => return None,
+    };
+    value.ok()
+}
+
+async fn list_items(pulse: &PulseHandle, kind: Kind) -> Result<Vec<PulseAddMessage<'static>>, String> {
+    let mut items = pulse.list(kind.list()).await.map_err(|err| format!("Failed to list {}s: {}", kind.label(), err))?;
+    items.sort_by_key(|item| item.index());
+    Ok(items)
+}
+
+async fn list(pulse: &PulseHandle, kind: Kind) -> Result<Vec<Object>, String> {
+    Ok(list_items(pulse, kind).await?.into_iter().filter_map(Object::from_message).collect())
+}
+
+async fn find(pulse: &PulseHandle, kind: Kind, target: &str) -> Result<Object, String> {
+    let objects = list(pulse, kind).await?;
+    let index = target.parse::<u32>().ok();
+    objects
+        .into_iter()
+        .find(|o| Some(o.index) == index || o.name == target)
+        .ok_or_else(|| format!("No {} {}", kind.label(), target))
+}
+
+async fn execute(pulse: &PulseHandle, command: PulseCommand) -> Result<(), String> {
+    if pulse.execute(command.clone()).await {
+        Ok(())
+    } else {
+        Err(format!("Failed: {:?}", command))
+    }
+}
+
+fn parse_percent(percent: &str) -> Result<f64, String> {
+    let max = Volume::MAX.0 as f64 * 100.0 / Volume::NORMAL.0 as f64;
+    percent
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .ok()
+        .filter(|p| p.is_finite() && *p >= 0.0 && *p <= max)
+        .ok_or_else(|| format!("Not a volume between 0% and {:.0}%: {}", max.floor(), percent))
+}
+
+fn describe(msg: &PulseMessage) -> Option<String> {
+    match msg {
+        PulseMessage::MsgAdd { msg, .. } => match msg {
+            PulseAddMessage::MsgCard(info) => {
+                let card = Card::from(info);
+                let profile = card.active_profile.map(|p| p.name).unwrap_or_else(|| "off".to_string());
+                Some(format!("card #{}\t{}\t{}", card.index, card.name, profile))
+            }
+            PulseAddMessage::MsgClient(info) => {
+                let client = Client::from(info);
+                Some(format!("client #{}\t{}", client.index, client.name))
+            }
+            PulseAddMessage::MsgModule(info) => {
+                let module = Module::from(info);
+                let mut line = format!("module #{}\t{}", module.index, module.name);
+                if !module.argument.is_empty() {
+                    line.push_str(&format!("\t{}", module.argument));
+                }
+                Some(line)
+            }
+            _ => Object::from_message(msg.clone()).map(|object| format!("{} {}", object.kind.label(), object.line())),
+        },
+        PulseMessage::MsgDel { id } => Some(format!("{} #{} removed", facility_tag(id), id.index())),
+        PulseMessage::MsgServer { info } => Some(format!(
+            "server {} default sink {} default source {}",
+            info.server_name.as_ref().map(|n| n.to_string()).unwrap_or_default(),
+            info.default_sink_name.as_ref().map(|n| n.to_string()).unwrap_or_default(),
+            info.default_source_name.as_ref().map(|n| n.to_string()).unwrap_or_default(),
+        )),
+        PulseMessage::MsgError { error } => Some(format!("error {}", error)),
+        PulseMessage::MsgConnection { state: ConnectionState::Connecting } => Some("connecting".to_string()),
+        PulseMessage::MsgConnection { state: ConnectionState::Ready } => Some("connected".to_string()),
+        PulseMessage::MsgConnection { state: ConnectionState::Disconnected { error, retry_in } } => Some(format!(
+            "disconnected ({}), retrying in {:?}",
+            error.map(|e| e.to_string()).unwrap_or_else(|| "no error".to_string()),
+            retry_in
+        )),
+        PulseMessage::MsgSyncComplete => Some("synced".to_string()),
+        PulseMessage::MsgCommandResult { .. } | PulseMessage::MsgPeaks { .. } => None,
+    }
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let (pulse, mut messages) = PulseHandle::spawn(args.server.clone());
+    let command: Vec<&str> = args.command.iter().map(|s| s.as_str()).collect();
+    if let ["watch"] | ["snapshot"] = command.as_slice() {
+        // A snapshot is the listing every connection starts with, and nothing after.
+        let snapshot = command == ["snapshot"];
+        while let Some(msg) = messages.next().await {
+            if snapshot {
+                match &msg {
+                    PulseMessage::MsgSyncComplete => break,
+                    PulseMessage::MsgConnection { state: ConnectionState::Disconnected { error, .. } } => {
+                        return Err(format!(
+                            "Lost the connection: {}",
+                            error.map(|e| e.to_string()).unwrap_or_else(|| "no error".to_string())
+                        ));
+                    }
+                    PulseMessage::MsgConnection { .. } => continue,
+                    _ => (),
+                }
+            }
+            let line = if args.json {
+                Event::from_message(&msg).and_then(|event| serde_json::to_string(&event).ok())
+            } else {
+                describe(&msg)
+            };
+            if let Some(line) = line {
+                println!("{}", line);
+            }
+        }
+        return Ok(());
+    }
+    // Nobody is interested in the events, but the pulse thread stops when the receiver is gone.
+    tokio::spawn(messages.for_each(|_| ready(())));
+
+    match command.as_slice() {
+        ["list", kind] => {
+            let items = list_items(&pulse, Kind::parse(kind)?).await?;
+            if args.json {
+                println!("{}", Value::Array(items.iter().filter_map(json).collect()));
+            } else {
+                for object in items.into_iter().filter_map(Object::from_message) {
+                    println!("{}", object.line());
+                }
+            }
+            Ok(())
+        }
+        ["set-volume", kind, target, percent] => {
+            let object = find(&pulse, Kind::parse(kind)?, target).await?;
+            let fraction = parse_percent(percent)? / 100.0;
+            // Same as the GUI slider: the loudest channel gets the new volume, the balance stays.
+            let mut volume = object.volume;
+            set_volume_fraction(&mut volume, fraction);
+            execute(&pulse, PulseCommand::SetVolume(object.target(), volume)).await
+        }
+        ["mute", kind, target] | ["mute", kind, target, "toggle"] => {
+            let object = find(&pulse, Kind::parse(kind)?, target).await?;
+            execute(&pulse, PulseCommand::SetMute(object.target(), !object.mute)).await
+        }
+        ["mute", kind, target, state @ "on"] | ["mute", kind, target, state @ "off"] => {
+            let object = find(&pulse, Kind::parse(kind)?, target).await?;
+            execute(&pulse, PulseCommand::SetMute(object.target(), *state == "on")).await
+        }
+        ["move", kind, target, device] => match Kind::parse(kind)? {
+            // Device names are left to the server to resolve.
+            Kind::SinkInput => {
+                let stream = SinkInputId(find(&pulse, Kind::SinkInput, target).await?.index);
+                let command = match device.parse::<u32>() {
+                    Ok(sink) => PulseCommand::MoveSinkInput(stream, SinkId(sink)),
+                    Err(_) => PulseCommand::MoveSinkInputByName(stream, device.to_string()),
+                };
+                execute(&pulse, command).await
+            }
+            Kind::SourceOutput => {
+                let stream = SourceOutputId(find(&pulse, Kind::SourceOutput, target).await?.index);
+                let command = match device.parse::<u32>() {
+                    Ok(source) => PulseCommand::MoveSourceOutput(stream, SourceId(source)),
+                    Err(_) => PulseCommand::MoveSourceOutputByName(stream, device.to_string()),
+                };
+                execute(&pulse, command).await
+            }
+            _ => Err("Only sink-inputs and source-outputs can be moved".to_string()),
+        },
+        ["set-default", kind, target] => match Kind::parse(kind)? {
+            Kind::Sink => {
+                let sink = find(&pulse, Kind::Sink, target).await?;
+                execute(&pulse, PulseCommand::SetDefaultSink(sink.name)).await
+            }
+            Kind::Source => {
+                let source = find(&pulse, Kind::Source, target).await?;
+                execute(&pulse, PulseCommand::SetDefaultSource(source.name)).await
+            }
+            _ => Err("Only sinks and sources can be the default".to_string()),
+        },
+        ["load-module", name, arguments @ ..] => {
+            let module = RawModule { name: name.to_string(), arguments: arguments.join(" ") };
+            match pulse.load_module(&module).await {
+                Some(ModuleId(index)) => {
+                    println!("{}", index);
+                    Ok(())
+                }
+                None => Err(format!("Failed to load {}", name)),
+            }
+        }
+        ["unload-module", index] => {
+            let index = index.parse::<u32>().map_err(|_| format!("Not a module index: {}", index))?;
+            execute(&pulse, PulseCommand::UnloadModule(ModuleId(index))).await
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            exit(2);
+        }
+    };
+    if let Err(err) = run(args).await {
+        eprintln!("{}", err);
+        exit(1);
+    }
+}
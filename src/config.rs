@@ -0,0 +1,92 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: pulsecontrol [--server SERVER]...
+
+  -s, --server SERVER   Pulse server to connect to, e.g. unix:/run/test/pulse/native or tcp:host:4713.
+                        Repeat to show several servers side by side. Overrides $PULSE_SERVER
+                        and the `server` entries in the config file.
+  -h, --help            Show this message.";
+
+/// Where to find the pulse servers. Empty leaves the choice to libpulse, which is the local default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub servers: Vec<String>,
+}
+
+impl Config {
+    /// One entry per connection to make, `None` being the default server.
+    pub fn connections(&self) -> Vec<Option<String>> {
+        if self.servers.is_empty() {
+            vec![None]
+        } else {
+            self.servers.iter().cloned().map(Some).collect()
+        }
+    }
+
+    /// Command line beats the environment beats the config file.
+    pub fn load() -> Result<Config, String> {
+        let mut config = match config_path() {
+            Some(path) if path.exists() => {
+                let content = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+                parse_file(&content).map_err(|err| format!("{}: {}", path.display(), err))?
+            }
+            _ => Config::default(),
+        };
+        // libpulse reads a list of fallbacks from here, so it is one connection however many
+        // addresses it holds.
+        if let Some(server) = env::var("PULSE_SERVER").ok().filter(|s| !s.is_empty()) {
+            config.servers = vec![server];
+        }
+        let mut servers = Vec::new();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-s" | "--server" => match args.next() {
+                    Some(server) => servers.push(server),
+                    None => return Err(format!("{} needs a value\n\n{}", arg, USAGE)),
+                },
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ if arg.starts_with("--server=") => servers.push(arg["--server=".len()..].to_string()),
+                _ => return Err(format!("Unknown argument {}\n\n{}", arg, USAGE)),
+            }
+        }
+        if !servers.is_empty() {
+            config.servers = servers;
+        }
+        Ok(config)
+    }
+}
+
+// $XDG_CONFIG_HOME/pulsecontrol/config, falling back to ~/.config.
+fn config_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("pulsecontrol").join("config"))
+}
+
+// `key = value` lines, `#` starts a comment. `server` may be given more than once.
+fn parse_file(content: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, value) = match line.find('=') {
+            Some(at) => (line[..at].trim(), line[at + 1..].trim()),
+            None => return Err(format!("line {}: expected `key = value`", number + 1)),
+        };
+        match key {
+            "server" => config.servers.push(value.to_string()),
+            _ => return Err(format!("line {}: unknown key {}", number + 1, key)),
+        }
+    }
+    Ok(config)
+}
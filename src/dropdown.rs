@@ -0,0 +1,75 @@
+use druid::{
+    BoxConstraints, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx,
+    Point, Rect, Size, UpdateCtx, Widget, WidgetPod,
+};
+
+/// Shows `header`, clicking it folds `menu` out below. Clicking into the menu folds it back in.
+pub struct Dropdown<T> {
+    header: WidgetPod<T, Box<dyn Widget<T>>>,
+    menu: WidgetPod<T, Box<dyn Widget<T>>>,
+    open: bool,
+}
+
+impl<T: Data> Dropdown<T> {
+    pub fn new(header: impl Widget<T> + 'static, menu: impl Widget<T> + 'static) -> Self {
+        Dropdown {
+            header: WidgetPod::new(Box::new(header)),
+            menu: WidgetPod::new(Box::new(menu)),
+            open: false,
+        }
+    }
+}
+
+impl<T: Data> Widget<T> for Dropdown<T> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
+        if let Event::MouseUp(mouse) = event {
+            if self.header.layout_rect().contains(mouse.pos) {
+                self.open = !self.open;
+                ctx.request_layout();
+            }
+        }
+        self.header.event(ctx, event, data, env);
+        if self.open {
+            self.menu.event(ctx, event, data, env);
+            if let Event::MouseUp(mouse) = event {
+                if self.menu.layout_rect().contains(mouse.pos) {
+                    self.open = false;
+                    ctx.request_layout();
+                }
+            }
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, env: &Env) {
+        self.header.lifecycle(ctx, event, data, env);
+        self.menu.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &T, data: &T, env: &Env) {
+        self.header.update(ctx, data, env);
+        self.menu.update(ctx, data, env);
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &T, env: &Env) -> Size {
+        let header_size = self.header.layout(ctx, bc, data, env);
+        self.header.set_layout_rect(ctx, data, env, Rect::from_origin_size(Point::ORIGIN, header_size));
+        if !self.open {
+            return header_size;
+        }
+        let menu_bc = BoxConstraints::new(Size::ZERO, Size::new(bc.max().width, f64::INFINITY));
+        let menu_size = self.menu.layout(ctx, &menu_bc, data, env);
+        let menu_origin = Point::new(0.0, header_size.height);
+        self.menu.set_layout_rect(ctx, data, env, Rect::from_origin_size(menu_origin, menu_size));
+        bc.constrain(Size::new(
+            header_size.width.max(menu_size.width),
+            header_size.height + menu_size.height,
+        ))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env) {
+        self.header.paint(ctx, data, env);
+        if self.open {
+            self.menu.paint(ctx, data, env);
+        }
+    }
+}
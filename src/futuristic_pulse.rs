@@ -1,21 +1,26 @@
 use crate::futuristic_pulse::PulseMessage::MsgDel;
 use crate::futuristic_pulse::PulseMessage::MsgAdd;
-use futures::channel::mpsc::Receiver;
+use crate::futuristic_pulse::PulseMessage::MsgServer;
+use crate::futuristic_pulse::PulseMessage::MsgCommandResult;
+use crate::futuristic_pulse::PulseMessage::MsgError;
+use crate::futuristic_pulse::PulseMessage::MsgConnection;
+use crate::futuristic_pulse::PulseMessage::MsgSyncComplete;
 // use pulse::mainloop::api::Mainloop as MainloopTrait; //Needs to be in scope
 
-use self::callback_future::{callback_stream_sink_info, callback_stream_source_info};
-use self::callback_future::{callback_stream_sink_input_info, callback_stream_source_output_info};
+use self::callback_future::{callback_future, callback_future_index, callback_future_success, callback_list_stream};
+use self::callback_future::{SuccessFuture, ValueFuture};
+use self::modules::ModuleSpec;
+use futures::future::{ready, BoxFuture, FutureExt};
 use pulse::context::introspect::*;
 
-use futures::channel::mpsc::channel;
 use futures::channel::mpsc::Sender;
-use futures::stream::once;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::stream::{empty, StreamExt};
 use libpulse_binding::context::introspect::SourceInfo;
 use pulse::context::subscribe::subscription_masks;
 use pulse::context::Context;
 use pulse::context::{
-    introspect::{SinkInfo, SinkInputInfo, SourceOutputInfo},
+    introspect::{CardInfo, ClientInfo, ModuleInfo, ServerInfo, SinkInfo, SinkInputInfo, SourceOutputInfo},
     subscribe::*,
 };
 use pulse::mainloop::standard::{IterateResult, Mainloop};
@@ -23,147 +28,383 @@ use pulse::proplist::Proplist;
 use std::cell::RefCell;
 use std::ops::Deref;
 use std::rc::Rc;
-use tokio::runtime::Runtime;
-use tokio::spawn;
+use std::thread::sleep;
+use std::time::Duration;
+use self::commands::{run_command, CommandOutput, PulseCommand};
+use self::peaks::{is_peak_meter, PeakMeters, PeakSource, PEAK_INTERVAL};
+use std::time::Instant;
+use futures::sink::SinkExt;
+use self::ids::{CardId, ClientId, ModuleId, ObjectRef, PulseId, SinkId, SinkInputId, SourceId, SourceOutputId};
+use pulse::volume::ChannelVolumes;
+use self::error::PulseError;
+use self::generations::Generations;
+use self::handle::{CommandRequest, ListKind, ListRequest, Request};
+use futures::stream::TryStreamExt;
+use futures::executor::{block_on, LocalPool};
+use futures::stream::LocalBoxStream;
+use self::wakeup::{MainloopWaker, WakingSpawner};
+use pulse::error::PAErr;
+use pulse::time::MicroSeconds;
+use std::rc::Weak;
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
+
+
+pub(crate) mod to_static;
+pub(crate) mod callback_future;
+pub(crate) mod ids;
+pub(crate) mod modules;
+pub(crate) mod commands;
+pub(crate) mod peaks;
+pub(crate) mod error;
+pub(crate) mod handle;
+pub(crate) mod model;
+pub(crate) mod generations;
+pub(crate) mod wakeup;
+
+// Reconnect delays, doubling after every attempt that didn't get the context ready.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Queries that can fail. Holds on to the context so failures can be told apart by their errno.
+#[derive(Clone)]
+pub(crate) struct PulseIntrospector {
+    context: Rc<RefCell<Context>>,
+}
 
+impl PulseIntrospector {
+    pub fn new(context: Rc<RefCell<Context>>) -> Self {
+        PulseIntrospector { context }
+    }
 
-pub mod to_static;
-pub mod callback_future;
+    fn introspect(&self) -> Introspector {
+        self.context.borrow_mut().introspect()
+    }
 
-// TODO different types for different ids
-// #[derive(Clone, Debug)]
-// struct SinkId(u32);
+    fn errno_source(&self) -> Weak<RefCell<Context>> {
+        Rc::downgrade(&self.context)
+    }
+}
 
-pub trait IntrospectorStream<T> {
-    fn stream_info_by_index(&self, index: u32) -> Receiver<T>;
-    fn stream_info_list(&self) -> Receiver<T>;
+pub(crate) trait IntrospectorStream<T> {
+    fn stream_info_by_index(&self, index: u32) -> UnboundedReceiver<Result<T, PulseError>>;
+    fn stream_info_list(&self) -> UnboundedReceiver<Result<T, PulseError>>;
 }
 
-impl IntrospectorStream<SinkInfo<'static>> for Introspector {
-    // fn stream_info_by_index(&self, index: u32) -> Chain<Receiver<SinkInfo<'static>>, Receiver<SinkInfo<'static>>> {
-    fn stream_info_by_index(&self, index: u32) -> Receiver<SinkInfo<'static>> {
-        let (callback, stream) = callback_stream_sink_info();
-        self.get_sink_info_by_index(index, callback);
-        stream
-    }
+// The getters only differ in the info type and their names.
+macro_rules! introspector_stream {
+    ($info:ident, $by_index:ident, $list:ident) => {
+        impl IntrospectorStream<$info<'static>> for PulseIntrospector {
+            fn stream_info_by_index(&self, index: u32) -> UnboundedReceiver<Result<$info<'static>, PulseError>> {
+                let (mut callback, stream) = callback_list_stream(self.errno_source());
+                self.introspect().$by_index(index, move |c| callback.handle(c));
+                stream
+            }
+
+            fn stream_info_list(&self) -> UnboundedReceiver<Result<$info<'static>, PulseError>> {
+                let (mut callback, stream) = callback_list_stream(self.errno_source());
+                self.introspect().$list(move |c| callback.handle(c));
+                stream
+            }
+        }
+    };
+}
 
-    fn stream_info_list(&self) -> Receiver<SinkInfo<'static>> {
-        let (callback, stream) = callback_stream_sink_info();
-        self.get_sink_info_list(callback);
-        stream
+introspector_stream!(SinkInfo, get_sink_info_by_index, get_sink_info_list);
+introspector_stream!(SourceInfo, get_source_info_by_index, get_source_info_list);
+introspector_stream!(SourceOutputInfo, get_source_output_info, get_source_output_info_list);
+introspector_stream!(SinkInputInfo, get_sink_input_info, get_sink_input_info_list);
+introspector_stream!(CardInfo, get_card_info_by_index, get_card_info_list);
+introspector_stream!(ClientInfo, get_client_info, get_client_info_list);
+introspector_stream!(ModuleInfo, get_module_info, get_module_info_list);
+
+pub(crate) trait ServerInfoFuture {
+    fn server_info(&self) -> ValueFuture<ServerInfo<'static>>;
+}
+
+impl ServerInfoFuture for PulseIntrospector {
+    fn server_info(&self) -> ValueFuture<ServerInfo<'static>> {
+        let (mut callback, future) = callback_future();
+        self.introspect().get_server_info(move |info| callback.handle(info));
+        future
     }
 }
 
-impl IntrospectorStream<SourceInfo<'static>> for Introspector {
-    fn stream_info_by_index(&self, index: u32) -> Receiver<SourceInfo<'static>> {
-        let (callback, stream) = callback_stream_source_info();
-        self.get_source_info_by_index(index, callback);
-        stream
+pub(crate) trait IntrospectorControl {
+    fn set_card_profile(&mut self, card: CardId, profile: &str) -> SuccessFuture;
+    fn load_module(&mut self, module: &dyn ModuleSpec) -> BoxFuture<'static, Option<ModuleId>>;
+    fn unload_module(&mut self, module: ModuleId) -> SuccessFuture;
+    fn set_volume(&mut self, target: &ObjectRef, volume: &ChannelVolumes) -> SuccessFuture;
+    fn set_mute(&mut self, target: &ObjectRef, mute: bool) -> SuccessFuture;
+    fn move_sink_input(&mut self, sink_input: SinkInputId, sink: SinkId) -> SuccessFuture;
+    fn move_sink_input_by_sink_name(&mut self, sink_input: SinkInputId, sink: &str) -> SuccessFuture;
+    fn move_source_output(&mut self, source_output: SourceOutputId, source: SourceId) -> SuccessFuture;
+    fn move_source_output_by_source_name(&mut self, source_output: SourceOutputId, source: &str) -> SuccessFuture;
+    fn set_port(&mut self, device: &ObjectRef, port: &str) -> SuccessFuture;
+}
+
+impl IntrospectorControl for Introspector {
+    fn set_card_profile(&mut self, card: CardId, profile: &str) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        self.set_card_profile_by_index(card.0, profile, Some(Box::new(callback)));
+        future
     }
 
-    fn stream_info_list(&self) -> Receiver<SourceInfo<'static>> {
-        let (callback, stream) = callback_stream_source_info();
-        self.get_source_info_list(callback);
-        stream
+    fn load_module(&mut self, module: &dyn ModuleSpec) -> BoxFuture<'static, Option<ModuleId>> {
+        let (callback, future) = callback_future_index();
+        Introspector::load_module(self, module.name(), &module.arguments(), callback);
+        future.map(|index| index.map(ModuleId)).boxed()
     }
-}
 
-impl IntrospectorStream<SourceOutputInfo<'static>> for Introspector {
-    fn stream_info_by_index(&self, index: u32) -> Receiver<SourceOutputInfo<'static>> {
-        let (callback, stream) = callback_stream_source_output_info();
-        self.get_source_output_info(index, callback);
-        stream
+    fn unload_module(&mut self, module: ModuleId) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        Introspector::unload_module(self, module.0, callback);
+        future
     }
 
-    fn stream_info_list(&self) -> Receiver<SourceOutputInfo<'static>> {
-        let (callback, stream) = callback_stream_source_output_info();
-        self.get_source_output_info_list(callback);
-        stream
+    fn set_volume(&mut self, target: &ObjectRef, volume: &ChannelVolumes) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        let callback: Option<Box<dyn FnMut(bool)>> = Some(Box::new(callback));
+        match target {
+            ObjectRef::Sink(id) => self.set_sink_volume_by_index(id.0, volume, callback),
+            ObjectRef::SinkName(name) => self.set_sink_volume_by_name(name, volume, callback),
+            ObjectRef::Source(id) => self.set_source_volume_by_index(id.0, volume, callback),
+            ObjectRef::SourceName(name) => self.set_source_volume_by_name(name, volume, callback),
+            ObjectRef::SinkInput(id) => self.set_sink_input_volume(id.0, volume, callback),
+            ObjectRef::SourceOutput(id) => self.set_source_output_volume(id.0, volume, callback),
+        };
+        future
+    }
+
+    fn set_mute(&mut self, target: &ObjectRef, mute: bool) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        let callback: Option<Box<dyn FnMut(bool)>> = Some(Box::new(callback));
+        match target {
+            ObjectRef::Sink(id) => self.set_sink_mute_by_index(id.0, mute, callback),
+            ObjectRef::SinkName(name) => self.set_sink_mute_by_name(name, mute, callback),
+            ObjectRef::Source(id) => self.set_source_mute_by_index(id.0, mute, callback),
+            ObjectRef::SourceName(name) => self.set_source_mute_by_name(name, mute, callback),
+            ObjectRef::SinkInput(id) => self.set_sink_input_mute(id.0, mute, callback),
+            ObjectRef::SourceOutput(id) => self.set_source_output_mute(id.0, mute, callback),
+        };
+        future
+    }
+
+    fn move_sink_input(&mut self, sink_input: SinkInputId, sink: SinkId) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        self.move_sink_input_by_index(sink_input.0, sink.0, Some(Box::new(callback)));
+        future
     }
-}
 
-impl IntrospectorStream<SinkInputInfo<'static>> for Introspector {
-    fn stream_info_by_index(&self, index: u32) -> Receiver<SinkInputInfo<'static>> {
-        let (callback, stream) = callback_stream_sink_input_info();
-        self.get_sink_input_info(index, callback);
-        stream
+    fn move_sink_input_by_sink_name(&mut self, sink_input: SinkInputId, sink: &str) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        self.move_sink_input_by_name(sink_input.0, sink, Some(Box::new(callback)));
+        future
     }
 
-    fn stream_info_list(&self) -> Receiver<SinkInputInfo<'static>> {
-        let (callback, stream) = callback_stream_sink_input_info();
-        self.get_sink_input_info_list(callback);
-        stream
+    fn move_source_output(&mut self, source_output: SourceOutputId, source: SourceId) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        self.move_source_output_by_index(source_output.0, source.0, Some(Box::new(callback)));
+        future
+    }
+
+    fn move_source_output_by_source_name(&mut self, source_output: SourceOutputId, source: &str) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        self.move_source_output_by_name(source_output.0, source, Some(Box::new(callback)));
+        future
+    }
+
+    fn set_port(&mut self, device: &ObjectRef, port: &str) -> SuccessFuture {
+        let (callback, future) = callback_future_success();
+        let callback: Option<Box<dyn FnMut(bool)>> = Some(Box::new(callback));
+        match device {
+            ObjectRef::Sink(id) => {
+                self.set_sink_port_by_index(id.0, port, callback);
+            }
+            ObjectRef::SinkName(name) => {
+                self.set_sink_port_by_name(name, port, callback);
+            }
+            ObjectRef::Source(id) => {
+                self.set_source_port_by_index(id.0, port, callback);
+            }
+            ObjectRef::SourceName(name) => {
+                self.set_source_port_by_name(name, port, callback);
+            }
+            // Streams don't have ports, dropping the callback fails the future.
+            ObjectRef::SinkInput(_) | ObjectRef::SourceOutput(_) => (),
+        };
+        future
+    }
+}
+
+/// Keeps a connection to the server up until nobody listens anymore. Every new connection starts
+/// over with the full listing, ending in `MsgSyncComplete`, so the receiving end should drop its
+/// state on `Disconnected`.
+/// Usually started through `PulseHandle::spawn`.
+/// `server` takes anything libpulse understands, `None` is the default server.
+pub(crate) fn init_pulse<'a>(
+    server: Option<String>,
+    sender: Sender<PulseMessage<'static>>,
+    mut commands: UnboundedReceiver<Request>,
+    waker: MainloopWaker,
+) -> () {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        if !send_blocking(&sender, MsgConnection { state: ConnectionState::Connecting }) {
+            return;
+        }
+        let error = match run_connection(server.as_deref(), &sender, &mut commands, &waker) {
+            Disconnect::Closed => return,
+            Disconnect::Lost { was_ready, error } => {
+                if was_ready {
+                    backoff = INITIAL_BACKOFF;
+                }
+                error
+            }
+        };
+        eprintln!("Lost the pulse connection to {:?}: {:?}, retrying in {:?}", server, error, backoff);
+        let state = ConnectionState::Disconnected { error, retry_in: backoff };
+        if !send_blocking(&sender, MsgConnection { state })
+            || !reject_commands_for(&sender, &mut commands, &waker, backoff)
+        {
+            return;
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
     }
 }
 
+enum Disconnect {
+    /// The other end of a channel is gone, nobody is interested anymore.
+    Closed,
+    Lost { was_ready: bool, error: Option<PulseError> },
+}
 
-pub fn init_pulse<'a>(sender: Sender<PulseMessage<'static>>) -> () {
-    let spec = pulse::sample::Spec {
-        format: pulse::sample::SAMPLE_S16NE,
-        channels: 2,
-        rate: 44100,
+fn send_blocking(sender: &Sender<PulseMessage<'static>>, message: PulseMessage<'static>) -> bool {
+    block_on(sender.clone().send(message)).is_ok()
+}
+
+// Nothing to run commands against while waiting for the next attempt, fail them right away.
+// Dropping a list request fails it as well.
+fn reject_commands_for(
+    sender: &Sender<PulseMessage<'static>>,
+    commands: &mut UnboundedReceiver<Request>,
+    waker: &MainloopWaker,
+    delay: Duration,
+) -> bool {
+    // Without a server the mainloop has nothing to do but wait for requests and the delay.
+    let mut mainloop = match Mainloop::new() {
+        Some(mainloop) => mainloop,
+        None => {
+            sleep(delay);
+            return true;
+        }
     };
-    assert!(spec.is_valid());
+    let _attached = waker.attach(&mainloop);
+    let until = Instant::now() + delay;
+    loop {
+        loop {
+            match commands.try_next() {
+                Ok(Some(Request::List(_))) => (),
+                Ok(Some(Request::Command(request))) => {
+                    let command = request.answer(CommandOutput::Failed);
+                    if !send_blocking(sender, MsgCommandResult { command, success: false }) {
+                        return false;
+                    }
+                }
+                Ok(None) => return false,
+                Err(_) => break,
+            }
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) {
+            return true;
+        }
+        if let Err(err) = wait(&mut mainloop, Some(left)) {
+            eprintln!("Failed to wait for requests {:?}", err);
+            sleep(left);
+        }
+    }
+}
+
+// Blocks until the server or a `PulseHandle` has something for the mainloop, or `timeout` is up.
+fn wait(mainloop: &mut Mainloop, timeout: Option<Duration>) -> Result<(), PAErr> {
+    mainloop.prepare(timeout.map(|timeout| MicroSeconds(timeout.as_micros() as u64)))?;
+    mainloop.poll()?;
+    mainloop.dispatch().map(|_| ())
+}
+
+fn context_error(context: &Rc<RefCell<Context>>) -> Option<PulseError> {
+    Some(PulseError(context.borrow().errno()))
+}
+
+fn run_connection(
+    server: Option<&str>,
+    sender: &Sender<PulseMessage<'static>>,
+    commands: &mut UnboundedReceiver<Request>,
+    waker: &MainloopWaker,
+) -> Disconnect {
+    let lost = |error| Disconnect::Lost { was_ready: false, error };
 
     let mut proplist = Proplist::new().unwrap();
     proplist
         .set_str(pulse::proplist::properties::APPLICATION_NAME, "FooApp")
         .unwrap();
 
-    let mainloop = Rc::new(RefCell::new(
-        Mainloop::new().expect("Failed to create mainloop"),
-    ));
+    let mainloop = match Mainloop::new() {
+        Some(mainloop) => Rc::new(RefCell::new(mainloop)),
+        None => return lost(None),
+    };
+    let _attached = waker.attach(&mainloop.borrow());
 
-    let context = Rc::new(RefCell::new(
-        Context::new_with_proplist(mainloop.borrow().deref(), "PulseControlContext", &proplist)
-            .expect("Failed to create new context"),
-    ));
+    let context = match Context::new_with_proplist(mainloop.borrow().deref(), "PulseControlContext", &proplist) {
+        Some(context) => Rc::new(RefCell::new(context)),
+        None => return lost(None),
+    };
 
-    context
-        .borrow_mut()
-        .connect(None, pulse::context::flags::NOFLAGS, None)
-        .expect("Failed to connect context");
+    if let Err(err) = context.borrow_mut().connect(server, pulse::context::flags::NOFLAGS, None) {
+        return lost(Some(PulseError(err)));
+    }
 
-    // recv implements Stream
-    let (mut send, recv): (Sender<RawPulseMessage>, Receiver<RawPulseMessage>) = channel(1024); // TODO channel size
+    // recv implements Stream. Unbounded, as the subscribe callback can't wait. Bursts are
+    // coalesced on the receiving end.
+    let (send, recv): (UnboundedSender<RawPulseMessage>, UnboundedReceiver<RawPulseMessage>) = unbounded();
 
     // Wait for context to be ready
     loop {
-        match mainloop.borrow_mut().iterate(false) {
-            IterateResult::Quit(_) | IterateResult::Err(_) => {
-                panic!("Iterate state was not success, quitting...");
-            }
-            IterateResult::Success(_) => {}
-        }
+        match mainloop.borrow_mut().iterate(true) {
+            IterateResult::Quit(_) => return lost(None),
+            IterateResult::Err(err) => return lost(Some(PulseError(err))),
+            IterateResult::Success(_) => (),
+        };
         match context.borrow().get_state() {
             pulse::context::State::Ready => {
                 break;
             }
             pulse::context::State::Failed | pulse::context::State::Terminated => {
-                panic!("Context state failed/terminated, quitting...");
+                return lost(context_error(&context));
             }
             _ => {}
         }
     }
+    if !send_blocking(sender, MsgConnection { state: ConnectionState::Ready }) {
+        return Disconnect::Closed;
+    }
+    let lost = |error| Disconnect::Lost { was_ready: true, error };
 
     let interest = subscription_masks::ALL;
 
     context
         .borrow_mut()
         .set_subscribe_callback(Some(Box::new(move |fac, op, i| match (fac, op, i) {
-            (Some(facility), Some(operation), index) => match send.try_send(RawPulseMessage {
-                facility,
-                operation,
-                index,
-            }) {
-                Ok(_) => (),
-                Err(err) => eprintln!("Got error {}", err),
-            },
+            (Some(facility), Some(operation), index) => {
+                // Only fails when the connection is being torn down.
+                let _ = send.unbounded_send(RawPulseMessage { facility, operation, index });
+            }
             _ => eprintln!("Got weird Message: {:?} | {:?} | {:?}", fac, op, i),
         })));
     eprintln!("Set callback.");
 
-    let introspector = context.borrow_mut().introspect();
+    let introspector = PulseIntrospector::new(context.clone());
 
     let _op = context.borrow_mut().subscribe(
         interest, // Our interest mask
@@ -172,82 +413,311 @@ pub fn init_pulse<'a>(sender: Sender<PulseMessage<'static>>) -> () {
         },
     );
 
-    let init_sink_stream = introspector
-        .stream_info_list()
-        .map(|info: SinkInfo| MsgAdd {
-            id: info.index,
-            msg: PulseAddMessage::MsgSink(info),
-        })
-        .boxed();
-    let init_source_stream = introspector
-        .stream_info_list()
-        .map(|info: SourceInfo| MsgAdd {
-            id: info.index,
-            msg: PulseAddMessage::MsgSource(info),
-        })
-        .boxed();
-    let init_sink_input_stream = introspector
-        .stream_info_list()
-        .map(|info: SinkInputInfo| MsgAdd {
-            id: info.index,
-            msg: PulseAddMessage::MsgSinkInput(info),
-        })
-        .boxed();
-    let init_source_output_stream = introspector
+    // The queries hold on to the context, so they are driven from this thread as well.
+    let mut pool = LocalPool::new();
+    let spawner = WakingSpawner::new(pool.spawner(), waker.clone());
+
+    // Everything describing the server's objects goes through here, in the order it was found to
+    // be fresh. Init lists and queries for live events run side by side, so without it a late
+    // answer could overtake the removal of its object.
+    let (out, out_recv) = unbounded();
+    let live_state = Rc::new(RefCell::new(LiveState::default()));
+    if let Err(err) = spawner.spawn_local(out_recv.map(Ok).forward(sender.clone()).map(|_| ())) {
+        eprintln!("Failed to spawn the pulse stream {:?}", err);
+        return lost(None);
+    }
+
+    let asked_at = live_state.borrow().generations.now();
+    let init_stream = server_stream(&introspector)
+        .chain(introspector.stream_info_list().map(add_message::<SinkInfo>))
+        .chain(introspector.stream_info_list().map(add_message::<SourceInfo>))
+        .chain(introspector.stream_info_list().map(add_message::<SinkInputInfo>))
+        .chain(introspector.stream_info_list().map(add_message::<SourceOutputInfo>))
+        .chain(introspector.stream_info_list().map(add_message::<CardInfo>))
+        .chain(introspector.stream_info_list().map(add_message::<ClientInfo>))
+        .chain(introspector.stream_info_list().map(add_message::<ModuleInfo>))
+        .boxed_local();
+    let init = forward_fresh(init_stream, asked_at, true, live_state.clone(), out.clone()).map({
+        let live_state = live_state.clone();
+        let out = out.clone();
+        move |_| {
+            let mut live_state = live_state.borrow_mut();
+            live_state.listing_done = true;
+            live_state.check_synced(&out);
+        }
+    });
+    if let Err(err) = spawner.spawn_local(init) {
+        eprintln!("Failed to spawn the initial listing {:?}", err);
+        return lost(None);
+    }
+
+    let live = recv.for_each({
+        let introspector = introspector.clone();
+        let spawner = spawner.clone();
+        move |raw| {
+            let id = PulseId::new(raw.facility, raw.index);
+            live_state.borrow_mut().generations.event(id);
+            match (raw.facility, raw.operation) {
+                (_, Operation::Removed) => {
+                    if let Some(id) = id {
+                        // Nobody heard of our meters, so nobody needs to hear they are gone.
+                        if !live_state.borrow_mut().peak_meters.remove(&id) {
+                            let _ = out.unbounded_send(MsgDel { id });
+                        }
+                    }
+                    return ready(());
+                }
+                (Facility::SampleCache, _) => return ready(()),
+                _ => (),
+            }
+            // The answer to a query still on its way is stale now, it has to go again once done.
+            // However many events pile up meanwhile, that is one more query.
+            match live_state.borrow_mut().in_flight.entry(id) {
+                Entry::Occupied(mut again) => {
+                    again.insert(true);
+                    return ready(());
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(false);
+                }
+            }
+            let query = query_until_current(introspector.clone(), raw, id, live_state.clone(), out.clone());
+            if let Err(err) = spawner.spawn_local(query) {
+                eprintln!("Failed to spawn query {:?}", err);
+                live_state.borrow_mut().query_done(id, &out);
+            }
+            ready(())
+        }
+    });
+    if let Err(err) = spawner.spawn_local(live) {
+        eprintln!("Failed to spawn the live stream {:?}", err);
+        return lost(None);
+    }
+
+    let mut meters = PeakMeters::new(sender.clone());
+    let mut last_flush = Instant::now();
+    loop {
+        // Commands have to run here, the context must not leave this thread.
+        loop {
+            let CommandRequest { command, reply } = match commands.try_next() {
+                Ok(Some(Request::Command(request))) => request,
+                Ok(Some(Request::List(ListRequest { kind, reply }))) => {
+                    let list = list_stream(&introspector, kind).try_collect();
+                    let spawned = spawner.spawn_local(async move {
+                        // Nobody waiting for it anymore is fine.
+                        let _ = reply.send(list.await);
+                    });
+                    if let Err(err) = spawned {
+                        eprintln!("Failed to spawn list {:?}", err);
+                    }
+                    continue;
+                }
+                Ok(None) => return Disconnect::Closed,
+                Err(_) => break,
+            };
+            let result = run_command(&mut context.borrow_mut(), &mut meters, &command);
+            let mut sender = sender.clone();
+            let spawned = spawner.spawn_local(async move {
+                let output = result.await;
+                let success = output.success();
+                let command = CommandRequest { command, reply }.answer(output);
+                if let Err(err) = sender.send(MsgCommandResult { command, success }).await {
+                    eprintln!("Failed to send message {:?}", err);
+                }
+            });
+            if let Err(err) = spawned {
+                eprintln!("Failed to spawn command {:?}", err);
+            }
+        }
+        pool.run_until_stalled();
+        if last_flush.elapsed() >= PEAK_INTERVAL {
+            meters.flush();
+            last_flush = Instant::now();
+        }
+        // Without meters there is nothing to flush, so only the server or a request wake us up.
+        let timeout = if meters.is_empty() {
+            None
+        } else {
+            Some((last_flush + PEAK_INTERVAL).saturating_duration_since(Instant::now()))
+        };
+        if let Err(err) = wait(&mut mainloop.borrow_mut(), timeout) {
+            return lost(Some(PulseError(err)));
+        }
+        match context.borrow().get_state() {
+            pulse::context::State::Failed | pulse::context::State::Terminated => {
+                return lost(context_error(&context));
+            }
+            _ => {}
+        }
+        if sender.is_closed() {
+            return Disconnect::Closed;
+        }
+    }
+}
+
+fn list_of<T>(introspector: &PulseIntrospector) -> LocalBoxStream<'static, Result<PulseAddMessage<'static>, PulseError>>
+where
+    PulseIntrospector: IntrospectorStream<T>,
+    T: Into<PulseAddMessage<'static>> + 'static,
+{
+    introspector
         .stream_info_list()
-        .map(|info: SourceOutputInfo| MsgAdd {
-            id: info.index,
-            msg: PulseAddMessage::MsgSourceOutput(info),
+        .map(|result| result.map(Into::into))
+        .try_filter(|msg| ready(!is_peak_meter(msg)))
+        .boxed_local()
+}
+
+fn list_stream(
+    introspector: &PulseIntrospector,
+    kind: ListKind,
+) -> LocalBoxStream<'static, Result<PulseAddMessage<'static>, PulseError>> {
+    match kind {
+        ListKind::Sinks => list_of::<SinkInfo>(introspector),
+        ListKind::Sources => list_of::<SourceInfo>(introspector),
+        ListKind::SinkInputs => list_of::<SinkInputInfo>(introspector),
+        ListKind::SourceOutputs => list_of::<SourceOutputInfo>(introspector),
+        ListKind::Cards => list_of::<CardInfo>(introspector),
+        ListKind::Clients => list_of::<ClientInfo>(introspector),
+        ListKind::Modules => list_of::<ModuleInfo>(introspector),
+    }
+}
+
+// Turns a query result into the message the UI gets to see.
+fn add_message<'a, T: Into<PulseAddMessage<'a>>>(result: Result<T, PulseError>) -> PulseMessage<'a> {
+    match result {
+        Ok(info) => {
+            let msg = info.into();
+            MsgAdd { id: msg.id(), msg }
+        }
+        Err(error) => MsgError { error },
+    }
+}
+
+fn server_stream(introspector: &PulseIntrospector) -> LocalBoxStream<'static, PulseMessage<'static>> {
+    introspector
+        .server_info()
+        .into_stream()
+        .filter_map(ready)
+        .map(|info| MsgServer { info })
+        .boxed_local()
+}
+
+// Bookkeeping shared by the initial listing and the queries for live events.
+#[derive(Default)]
+struct LiveState {
+    generations: Generations,
+    // Objects with a query on its way, and whether another event for them came in since it was sent.
+    in_flight: HashMap<Option<PulseId>, bool>,
+    // Objects whose entry in the initial listing went stale while their query was on its way.
+    // Their state only arrives with that query, so the sync isn't complete before it is done.
+    stale_listed: HashSet<Option<PulseId>>,
+    // The record streams behind our meters, they are kept out of what is sent on.
+    peak_meters: HashSet<PulseId>,
+    listing_done: bool,
+    synced: bool,
+}
+
+impl LiveState {
+    fn query_done(&mut self, id: Option<PulseId>, out: &UnboundedSender<PulseMessage<'static>>) {
+        self.in_flight.remove(&id);
+        self.stale_listed.remove(&id);
+        self.check_synced(out);
+    }
+
+    fn check_synced(&mut self, out: &UnboundedSender<PulseMessage<'static>>) {
+        if self.listing_done && !self.synced && self.stale_listed.is_empty() {
+            self.synced = true;
+            let _ = out.unbounded_send(MsgSyncComplete);
+        }
+    }
+}
+
+// Passes on the answers to queries sent at `asked_at`, dropping those that went stale meanwhile.
+async fn forward_fresh(
+    answers: LocalBoxStream<'static, PulseMessage<'static>>,
+    asked_at: u64,
+    listing: bool,
+    live_state: Rc<RefCell<LiveState>>,
+    out: UnboundedSender<PulseMessage<'static>>,
+) {
+    answers
+        .for_each(move |msg| {
+            let id = match &msg {
+                MsgAdd { id, .. } => Some(Some(*id)),
+                MsgServer { .. } => Some(None),
+                _ => None,
+            };
+            let meter = matches!(&msg, MsgAdd { msg, .. } if is_peak_meter(msg));
+            let mut live_state = live_state.borrow_mut();
+            match id {
+                Some(Some(id)) if meter => {
+                    live_state.peak_meters.insert(id);
+                }
+                Some(id) if !live_state.generations.is_fresh(id, asked_at) => {
+                    if listing && live_state.in_flight.contains_key(&id) {
+                        live_state.stale_listed.insert(id);
+                    }
+                }
+                // Only fails once the connection is being torn down.
+                _ => {
+                    let _ = out.unbounded_send(msg);
+                }
+            }
+            ready(())
         })
-        .boxed();
-    let live_stream = recv.flat_map({
-        move |raw| match (raw.facility, raw.operation) {
-            (_, Operation::Removed) => once(async move { MsgDel { id: raw.index } }).boxed(),
-            (Facility::Sink, _) => introspector
-                .stream_info_by_index(raw.index)
-                .map(move |info| MsgAdd {
-                    id: raw.index,
-                    msg: PulseAddMessage::MsgSink(info),
-                })
-                .boxed(),
-            (Facility::Source, _) => introspector
-                .stream_info_by_index(raw.index)
-                .map(move |info| MsgAdd {
-                    id: raw.index,
-                    msg: PulseAddMessage::MsgSource(info),
-                })
-                .boxed(),
-            (Facility::SinkInput, _) => introspector
-                .stream_info_by_index(raw.index)
-                .map(move |info| MsgAdd {
-                    id: raw.index,
-                    msg: PulseAddMessage::MsgSinkInput(info),
-                })
-                .boxed(),
-            (Facility::SourceOutput, _) => introspector
-                .stream_info_by_index(raw.index)
-                .map(move |info| MsgAdd {
-                    id: raw.index,
-                    msg: PulseAddMessage::MsgSourceOutput(info),
-                })
-                .boxed(),
-            (Facility::Module, _) => empty().boxed(),
-            (Facility::Client, _) => empty().boxed(),
-            (Facility::SampleCache, _) => empty().boxed(),
-            (Facility::Server, _) => empty().boxed(),
-            (Facility::Card, _) => empty().boxed(),
+        .await
+}
+
+// Queries a changed object until the answer isn't overtaken by another event for it.
+async fn query_until_current(
+    introspector: PulseIntrospector,
+    raw: RawPulseMessage,
+    id: Option<PulseId>,
+    live_state: Rc<RefCell<LiveState>>,
+    out: UnboundedSender<PulseMessage<'static>>,
+) {
+    loop {
+        let asked_at = live_state.borrow().generations.now();
+        let answers = live_query(&introspector, &raw);
+        forward_fresh(answers, asked_at, false, live_state.clone(), out.clone()).await;
+        let mut live_state = live_state.borrow_mut();
+        match live_state.in_flight.get_mut(&id) {
+            Some(again) if *again => *again = false,
+            _ => {
+                live_state.query_done(id, &out);
+                return;
+            }
         }
-    });
+    }
+}
 
-    let pulse_stream = init_sink_stream
-        .chain(init_source_stream)
-        .chain(init_sink_input_stream)
-        .chain(init_source_output_stream)
-        .chain(live_stream);
+fn live_query(introspector: &PulseIntrospector, raw: &RawPulseMessage) -> LocalBoxStream<'static, PulseMessage<'static>> {
+    match raw.facility {
+        Facility::Sink => by_index::<SinkInfo>(introspector, raw.index),
+        Facility::Source => by_index::<SourceInfo>(introspector, raw.index),
+        Facility::SinkInput => by_index::<SinkInputInfo>(introspector, raw.index),
+        Facility::SourceOutput => by_index::<SourceOutputInfo>(introspector, raw.index),
+        Facility::Module => by_index::<ModuleInfo>(introspector, raw.index),
+        Facility::Client => by_index::<ClientInfo>(introspector, raw.index),
+        // Nothing shows samples.
+        Facility::SampleCache => empty().boxed_local(),
+        Facility::Server => server_stream(introspector),
+        Facility::Card => by_index::<CardInfo>(introspector, raw.index),
+    }
+}
 
-    let rt = Runtime::new().unwrap(); // TODO
-    rt.enter(|| spawn(pulse_stream.map(|x| Ok(x)).forward(sender)));
-    mainloop.borrow_mut().run().expect("Mainloop failed");
+// Fetches a changed object. It may have been removed again before the query ran, its removal
+// event is on the way then, so that error isn't worth reporting.
+fn by_index<T>(introspector: &PulseIntrospector, index: u32) -> LocalBoxStream<'static, PulseMessage<'static>>
+where
+    PulseIntrospector: IntrospectorStream<T>,
+    T: Into<PulseAddMessage<'static>> + 'static,
+{
+    introspector
+        .stream_info_by_index(index)
+        .filter(|result| ready(!matches!(result, Err(error) if error.is_no_entity())))
+        .map(add_message)
+        .boxed_local()
 }
 
 #[derive(Clone, Debug)]
@@ -262,8 +732,24 @@ unsafe impl Sync for RawPulseMessage {}
 
 #[derive(Clone, Debug)]
 pub enum PulseMessage<'a> {
-    MsgAdd { id: u32, msg: PulseAddMessage<'a> }, // add or update
-    MsgDel { id: u32 },
+    MsgAdd { id: PulseId, msg: PulseAddMessage<'a> }, // add or update
+    MsgDel { id: PulseId },
+    MsgServer { info: ServerInfo<'a> },
+    MsgCommandResult { command: PulseCommand, success: bool },
+    MsgPeaks { peaks: Vec<(PeakSource, f32)> },
+    MsgError { error: PulseError },
+    MsgConnection { state: ConnectionState },
+    /// The initial listing after `Ready` is done, objects that changed while it ran included.
+    /// Changes don't wait for it, they can come earlier.
+    MsgSyncComplete,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Ready,
+    /// Everything known about the server so far is stale.
+    Disconnected { error: Option<PulseError>, retry_in: Duration },
 }
 
 #[derive(Clone, Debug)]
@@ -272,4 +758,176 @@ pub enum PulseAddMessage<'a> {
     MsgSource(SourceInfo<'a>),
     MsgSourceOutput(SourceOutputInfo<'a>),
     MsgSinkInput(SinkInputInfo<'a>),
-}
\ No newline at end of file
+    MsgCard(CardInfo<'a>),
+    MsgClient(ClientInfo<'a>),
+    MsgModule(ModuleInfo<'a>),
+}
+
+impl<'a> PulseAddMessage<'a> {
+    pub fn index(&self) -> u32 {
+        match self {
+            PulseAddMessage::MsgSink(info) => info.index,
+            PulseAddMessage::MsgSource(info) => info.index,
+            PulseAddMessage::MsgSourceOutput(info) => info.index,
+            PulseAddMessage::MsgSinkInput(info) => info.index,
+            PulseAddMessage::MsgCard(info) => info.index,
+            PulseAddMessage::MsgClient(info) => info.index,
+            PulseAddMessage::MsgModule(info) => info.index,
+        }
+    }
+    pub fn id(&self) -> PulseId {
+        match self {
+            PulseAddMessage::MsgSink(info) => PulseId::Sink(SinkId(info.index)),
+            PulseAddMessage::MsgSource(info) => PulseId::Source(SourceId(info.index)),
+            PulseAddMessage::MsgSourceOutput(info) => PulseId::SourceOutput(SourceOutputId(info.index)),
+            PulseAddMessage::MsgSinkInput(info) => PulseId::SinkInput(SinkInputId(info.index)),
+            PulseAddMessage::MsgCard(info) => PulseId::Card(CardId(info.index)),
+            PulseAddMessage::MsgClient(info) => PulseId::Client(ClientId(info.index)),
+            PulseAddMessage::MsgModule(info) => PulseId::Module(ModuleId(info.index)),
+        }
+    }
+}
+
+impl<'a> From<SinkInfo<'a>> for PulseAddMessage<'a> {
+    fn from(info: SinkInfo<'a>) -> Self {
+        PulseAddMessage::MsgSink(info)
+    }
+}
+
+impl<'a> From<SourceInfo<'a>> for PulseAddMessage<'a> {
+    fn from(info: SourceInfo<'a>) -> Self {
+        PulseAddMessage::MsgSource(info)
+    }
+}
+
+impl<'a> From<SourceOutputInfo<'a>> for PulseAddMessage<'a> {
+    fn from(info: SourceOutputInfo<'a>) -> Self {
+        PulseAddMessage::MsgSourceOutput(info)
+    }
+}
+
+impl<'a> From<SinkInputInfo<'a>> for PulseAddMessage<'a> {
+    fn from(info: SinkInputInfo<'a>) -> Self {
+        PulseAddMessage::MsgSinkInput(info)
+    }
+}
+
+impl<'a> From<CardInfo<'a>> for PulseAddMessage<'a> {
+    fn from(info: CardInfo<'a>) -> Self {
+        PulseAddMessage::MsgCard(info)
+    }
+}
+
+impl<'a> From<ClientInfo<'a>> for PulseAddMessage<'a> {
+    fn from(info: ClientInfo<'a>) -> Self {
+        PulseAddMessage::MsgClient(info)
+    }
+}
+
+impl<'a> From<ModuleInfo<'a>> for PulseAddMessage<'a> {
+    fn from(info: ModuleInfo<'a>) -> Self {
+        PulseAddMessage::MsgModule(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::iter;
+
+    fn client(index: u32) -> PulseMessage<'static> {
+        let proplist = Proplist::new().unwrap();
+        add_message(Ok(ClientInfo { index, name: None, owner_module: None, driver: None, proplist }))
+    }
+
+    fn client_id(index: u32) -> Option<PulseId> {
+        Some(PulseId::Client(ClientId(index)))
+    }
+
+    // What the live stream does with a change event before querying the object.
+    fn change(live_state: &Rc<RefCell<LiveState>>, id: Option<PulseId>) -> u64 {
+        let mut live_state = live_state.borrow_mut();
+        live_state.in_flight.insert(id, false);
+        live_state.generations.event(id)
+    }
+
+    fn forward(
+        live_state: &Rc<RefCell<LiveState>>,
+        out: &UnboundedSender<PulseMessage<'static>>,
+        answers: Vec<PulseMessage<'static>>,
+        asked_at: u64,
+        listing: bool,
+    ) {
+        let answers = iter(answers).boxed_local();
+        block_on(forward_fresh(answers, asked_at, listing, live_state.clone(), out.clone()));
+    }
+
+    fn sent(recv: &mut UnboundedReceiver<PulseMessage<'static>>) -> Vec<PulseMessage<'static>> {
+        let mut sent = Vec::new();
+        while let Ok(Some(msg)) = recv.try_next() {
+            sent.push(msg);
+        }
+        sent
+    }
+
+    #[test]
+    fn listing_entry_overtaken_by_change() {
+        let live_state = Rc::new(RefCell::new(LiveState::default()));
+        let (out, mut recv) = unbounded();
+        let listed_at = live_state.borrow().generations.now();
+        let asked_at = change(&live_state, client_id(1));
+
+        forward(&live_state, &out, vec![client(1), client(2)], listed_at, true);
+        let forwarded = sent(&mut recv);
+        assert!(matches!(forwarded.as_slice(), [MsgAdd { id: PulseId::Client(ClientId(2)), .. }]), "{:?}", forwarded);
+        assert!(live_state.borrow().stale_listed.contains(&client_id(1)));
+
+        forward(&live_state, &out, vec![client(1)], asked_at, false);
+        let forwarded = sent(&mut recv);
+        assert!(matches!(forwarded.as_slice(), [MsgAdd { id: PulseId::Client(ClientId(1)), .. }]), "{:?}", forwarded);
+    }
+
+    #[test]
+    fn late_reply_after_removal() {
+        let live_state = Rc::new(RefCell::new(LiveState::default()));
+        let (out, mut recv) = unbounded();
+        let asked_at = change(&live_state, client_id(3));
+        live_state.borrow_mut().generations.event(client_id(3));
+
+        forward(&live_state, &out, vec![client(3)], asked_at, false);
+        let forwarded = sent(&mut recv);
+        assert!(forwarded.is_empty(), "{:?}", forwarded);
+        // Only listing entries hold up the sync.
+        assert!(live_state.borrow().stale_listed.is_empty());
+    }
+
+    #[test]
+    fn sync_complete_held_until_stale_listed_is_empty() {
+        let live_state = Rc::new(RefCell::new(LiveState::default()));
+        let (out, mut recv) = unbounded();
+        let listed_at = live_state.borrow().generations.now();
+        change(&live_state, client_id(1));
+        change(&live_state, client_id(2));
+
+        forward(&live_state, &out, vec![client(1), client(2)], listed_at, true);
+        {
+            let mut live_state = live_state.borrow_mut();
+            live_state.listing_done = true;
+            live_state.check_synced(&out);
+        }
+        let forwarded = sent(&mut recv);
+        assert!(forwarded.is_empty(), "{:?}", forwarded);
+
+        live_state.borrow_mut().query_done(client_id(1), &out);
+        let forwarded = sent(&mut recv);
+        assert!(forwarded.is_empty(), "{:?}", forwarded);
+
+        live_state.borrow_mut().query_done(client_id(2), &out);
+        let forwarded = sent(&mut recv);
+        assert!(matches!(forwarded.as_slice(), [MsgSyncComplete]), "{:?}", forwarded);
+
+        // Once is enough.
+        live_state.borrow_mut().check_synced(&out);
+        assert!(sent(&mut recv).is_empty());
+    }
+}
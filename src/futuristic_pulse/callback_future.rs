@@ -1,110 +1,113 @@
-use futures::channel::mpsc::channel;
-use futures::channel::mpsc::Receiver;
-use libpulse_binding::{
-    callbacks::ListResult,
-    context::introspect::{
-        SinkInfo, SinkInputInfo, SourceInfo, SourceOutputInfo,
-    },
-};
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::channel::oneshot::Canceled;
+use futures::future::{FutureExt, Map};
+use libpulse_binding::callbacks::ListResult;
+use libpulse_binding::context::Context;
+use libpulse_binding::def::INVALID_INDEX;
+use std::cell::RefCell;
+use std::rc::Weak;
+use super::error::PulseError;
 use super::to_static::ToStatic;
 
-pub fn callback_stream_sink_info() -> (
-    impl FnMut(ListResult<&SinkInfo<'_>>),
-    Receiver<SinkInfo<'static>>,
-) {
-    let (mut sender, recv) = channel(64); // TODO channel size?
-    let cb = {
-        move |c: ListResult<&SinkInfo<'_>>| {
-            match c {
-                ListResult::Item(it) => match sender.try_send(it.to_static()) {
-                    Ok(_) => (),
-                    Err(err) => eprintln!("Failed to send message {:?}", err),
-                },
-                ListResult::End => sender.disconnect(),
-                ListResult::Error => {
-                    eprintln!("Got an error on the C callback.");
-                    sender.disconnect()
-                } // TODO
+/// Resolves to the index of the created object, `None` if the server refused.
+pub(crate) type IndexFuture = Map<oneshot::Receiver<u32>, fn(Result<u32, Canceled>) -> Option<u32>>;
+
+/// Resolves to whether the server accepted the operation. A dropped callback counts as failure.
+pub(crate) type SuccessFuture = Map<oneshot::Receiver<bool>, fn(Result<bool, Canceled>) -> bool>;
+
+/// Resolves to the single result of a query, `None` if the callback was dropped without one.
+pub(crate) type ValueFuture<S> = Map<oneshot::Receiver<S>, fn(Result<S, Canceled>) -> Option<S>>;
+
+// The introspect callbacks are higher ranked over the lifetime of the borrowed info, which a
+// generic closure can't express. So the adapters are structs with a generic `handle` method,
+// and every call site wraps them in a closure of its own:
+//
+//     let (mut callback, stream) = callback_list_stream(context);
+//     self.get_sink_info_list(move |c| callback.handle(c));
+
+/// Feeds list results into a channel. On failure the context's errno is sent as the last item.
+/// The channel is unbounded, the callback can't wait and every item counts.
+pub(crate) struct ListCallback<S> {
+    sender: UnboundedSender<Result<S, PulseError>>,
+    context: Weak<RefCell<Context>>,
+}
+
+impl<S> ListCallback<S> {
+    pub fn handle<T: ToStatic<Static = S>>(&mut self, c: ListResult<&T>) {
+        match c {
+            // Failing means nobody is waiting for the answer anymore.
+            ListResult::Item(it) => {
+                let _ = self.sender.unbounded_send(Ok(it.to_static()));
+            }
+            ListResult::End => self.sender.disconnect(),
+            ListResult::Error => {
+                // The callback runs from within the mainloop, nobody else holds the context then.
+                if let Some(context) = self.context.upgrade() {
+                    let error = PulseError(context.borrow().errno());
+                    let _ = self.sender.unbounded_send(Err(error));
+                }
+                self.sender.disconnect()
             }
         }
-    };
-    (cb, recv)
+    }
 }
 
-pub fn callback_stream_source_info() -> (
-    impl FnMut(ListResult<&SourceInfo<'_>>),
-    Receiver<SourceInfo<'static>>,
-) {
-    let (mut sender, recv) = channel(64); // TODO channel size?
-    let cb = {
-        move |c: ListResult<&SourceInfo<'_>>| match c {
-            ListResult::Item(it) => match sender.try_send(it.to_static()) {
-                Ok(_) => (),
-                Err(err) => eprintln!("Failed to send message {:?}", err),
-            },
-            ListResult::End => sender.disconnect(),
-            ListResult::Error => {
-                eprintln!("Got an error on the C callback.");
-                sender.disconnect()
-            } // TODO
+pub(crate) fn callback_list_stream<S>(
+    context: Weak<RefCell<Context>>,
+) -> (ListCallback<S>, UnboundedReceiver<Result<S, PulseError>>) {
+    let (sender, recv) = unbounded();
+    (ListCallback { sender, context }, recv)
+}
+
+pub(crate) struct ValueCallback<S> {
+    sender: Option<oneshot::Sender<S>>,
+}
+
+impl<S> ValueCallback<S> {
+    pub fn handle<T: ToStatic<Static = S>>(&mut self, it: &T) {
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(it.to_static());
         }
-    };
-    (cb, recv)
+    }
 }
 
-pub fn callback_stream_sink_input_info() -> (
-    impl FnMut(ListResult<&SinkInputInfo<'_>>),
-    Receiver<SinkInputInfo<'static>>,
-) {
-    let (mut sender, recv) = channel(64); // TODO channel size?
-    let cb = {
-        move |c: ListResult<&SinkInputInfo<'_>>| match c {
-            ListResult::Item(it) => match sender.try_send(it.to_static()) {
-                Ok(_) => (),
-                Err(err) => eprintln!("Failed to send message {:?}", err),
-            },
-            ListResult::End => sender.disconnect(),
-            ListResult::Error => {
-                eprintln!("Got an error on the C callback.");
-                sender.disconnect()
-            } // TODO
+fn canceled_is_none<S>(result: Result<S, Canceled>) -> Option<S> {
+    result.ok()
+}
+
+pub(crate) fn callback_future<S>() -> (ValueCallback<S>, ValueFuture<S>) {
+    let (sender, recv) = oneshot::channel();
+    let callback = ValueCallback { sender: Some(sender) };
+    (callback, recv.map(canceled_is_none as fn(Result<S, Canceled>) -> Option<S>))
+}
+
+fn canceled_is_failure(result: Result<bool, Canceled>) -> bool {
+    result.unwrap_or(false)
+}
+
+pub(crate) fn callback_future_success() -> (impl FnMut(bool), SuccessFuture) {
+    let (sender, recv) = oneshot::channel();
+    let mut sender = Some(sender);
+    let cb = move |success: bool| {
+        if let Some(sender) = sender.take() {
+            let _ = sender.send(success);
         }
     };
-    (cb, recv)
+    (cb, recv.map(canceled_is_failure as fn(Result<bool, Canceled>) -> bool))
 }
 
-pub fn callback_stream_source_output_info() -> (
-    impl FnMut(ListResult<&SourceOutputInfo<'_>>),
-    Receiver<SourceOutputInfo<'static>>,
-) {
-    let (mut sender, recv) = channel(64); // TODO channel size?
-    let cb = {
-        move |c: ListResult<&SourceOutputInfo<'_>>| match c {
-            ListResult::Item(it) => match sender.try_send(it.to_static()) {
-                Ok(_) => (),
-                Err(err) => eprintln!("Failed to send message {:?}", err),
-            },
-            ListResult::End => sender.disconnect(),
-            ListResult::Error => {
-                eprintln!("Got an error on the C callback.");
-                sender.disconnect()
-            } // TODO
+fn invalid_index_is_none(result: Result<u32, Canceled>) -> Option<u32> {
+    result.ok().filter(|index| *index != INVALID_INDEX)
+}
+
+pub(crate) fn callback_future_index() -> (impl FnMut(u32), IndexFuture) {
+    let (sender, recv) = oneshot::channel();
+    let mut sender = Some(sender);
+    let cb = move |index: u32| {
+        if let Some(sender) = sender.take() {
+            let _ = sender.send(index);
         }
     };
-    (cb, recv)
+    (cb, recv.map(invalid_index_is_none as fn(Result<u32, Canceled>) -> Option<u32>))
 }
-
-// pub fn callback_list_stream<T: ToStatic >() -> (impl FnMut(ListResult<&T>), Receiver<<T as ToStatic>::Static>) {
-//     let (mut sender, recv) = channel(1024); // TODO channel size?
-//     let cb = {
-//         move |c: ListResult<&T>| match c {
-//             ListResult::Item(it) => match sender.try_send(it.to_static()) {
-//                 Ok(_) => (),
-//                 Err(err) => eprintln!("Failed to send message {:?}", err),
-//             },
-//             ListResult::End => sender.disconnect(),
-//             ListResult::Error => (), // TODO
-//         }
-//     };
-//     (cb, recv)
-// }
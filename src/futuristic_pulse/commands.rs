@@ -0,0 +1,113 @@
+use super::callback_future::{callback_future_success, SuccessFuture};
+use futures::future::{BoxFuture, FutureExt};
+use super::ids::{CardId, ModuleId, ObjectRef, SinkId, SinkInputId, SourceId, SourceOutputId};
+use super::peaks::{PeakMeters, PeakSource};
+use super::modules::{ModuleSpec, RawModule};
+use super::IntrospectorControl;
+use pulse::context::Context;
+use pulse::volume::ChannelVolumes;
+
+/// Requests from the frontend, executed on the pulse thread.
+#[derive(Clone, Debug)]
+pub enum PulseCommand {
+    SetDefaultSink(String),
+    SetDefaultSource(String),
+    SetVolume(ObjectRef, ChannelVolumes),
+    SetMute(ObjectRef, bool),
+    MoveSinkInput(SinkInputId, SinkId),
+    MoveSourceOutput(SourceOutputId, SourceId),
+    /// Moves to the sink with that name, resolved by the server.
+    MoveSinkInputByName(SinkInputId, String),
+    MoveSourceOutputByName(SourceOutputId, String),
+    SetPort(ObjectRef, String),
+    /// Switches the card to the profile with that name, e.g. from A2DP to HSP.
+    SetCardProfile(CardId, String),
+    /// Outputs `ModuleLoaded` with the new module's index. See `PulseCommand::load_module` for
+    /// the typed builders.
+    LoadModule(RawModule),
+    UnloadModule(ModuleId),
+    StartPeakMeter(PeakSource),
+    StopPeakMeter(PeakSource),
+}
+
+impl PulseCommand {
+    pub fn load_module(module: &dyn ModuleSpec) -> PulseCommand {
+        PulseCommand::LoadModule(RawModule { name: module.name().to_string(), arguments: module.arguments() })
+    }
+}
+
+/// What a finished command hands back. Most commands only succeed or fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandOutput {
+    Failed,
+    Done,
+    ModuleLoaded(ModuleId),
+}
+
+impl CommandOutput {
+    pub fn success(self) -> bool {
+        self != CommandOutput::Failed
+    }
+}
+
+impl From<bool> for CommandOutput {
+    fn from(success: bool) -> Self {
+        if success {
+            CommandOutput::Done
+        } else {
+            CommandOutput::Failed
+        }
+    }
+}
+
+fn done(success: bool) -> SuccessFuture {
+    let (mut callback, future) = callback_future_success();
+    callback(success);
+    future
+}
+
+pub(crate) fn run_command(
+    context: &mut Context,
+    meters: &mut PeakMeters,
+    command: &PulseCommand,
+) -> BoxFuture<'static, CommandOutput> {
+    let success = match command {
+        PulseCommand::LoadModule(module) => {
+            // The inherent `Introspector::load_module` would shadow ours.
+            return IntrospectorControl::load_module(&mut context.introspect(), module)
+                .map(|module| module.map_or(CommandOutput::Failed, CommandOutput::ModuleLoaded))
+                .boxed();
+        }
+        PulseCommand::UnloadModule(module) => IntrospectorControl::unload_module(&mut context.introspect(), *module),
+        PulseCommand::SetDefaultSink(name) => {
+            let (callback, future) = callback_future_success();
+            context.set_default_sink(name, callback);
+            future
+        }
+        PulseCommand::SetDefaultSource(name) => {
+            let (callback, future) = callback_future_success();
+            context.set_default_source(name, callback);
+            future
+        }
+        PulseCommand::SetVolume(target, volume) => context.introspect().set_volume(target, volume),
+        PulseCommand::SetMute(target, mute) => context.introspect().set_mute(target, *mute),
+        PulseCommand::MoveSinkInput(sink_input, sink) => context.introspect().move_sink_input(*sink_input, *sink),
+        PulseCommand::MoveSourceOutput(source_output, source) => {
+            context.introspect().move_source_output(*source_output, *source)
+        }
+        PulseCommand::MoveSinkInputByName(sink_input, sink) => {
+            context.introspect().move_sink_input_by_sink_name(*sink_input, sink)
+        }
+        PulseCommand::MoveSourceOutputByName(source_output, source) => {
+            context.introspect().move_source_output_by_source_name(*source_output, source)
+        }
+        PulseCommand::SetPort(device, port) => context.introspect().set_port(device, port),
+        PulseCommand::SetCardProfile(card, profile) => context.introspect().set_card_profile(*card, profile),
+        PulseCommand::StartPeakMeter(source) => done(meters.start(context, *source)),
+        PulseCommand::StopPeakMeter(source) => {
+            meters.stop(*source);
+            done(true)
+        }
+    };
+    success.map(CommandOutput::from).boxed()
+}
@@ -0,0 +1,24 @@
+use pulse::error::{Code, PAErr};
+use std::fmt;
+
+/// A failed request, carrying what `Context::errno` said at the time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PulseError(pub PAErr);
+
+impl PulseError {
+    /// The object asked for doesn't exist (anymore).
+    pub fn is_no_entity(&self) -> bool {
+        self.0 == PAErr::from(Code::NoEntity)
+    }
+}
+
+impl fmt::Display for PulseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.to_string() {
+            Some(message) => write!(f, "{}", message),
+            None => write!(f, "Unknown pulse error {}", (self.0).0),
+        }
+    }
+}
+
+impl std::error::Error for PulseError {}
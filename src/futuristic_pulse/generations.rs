@@ -0,0 +1,63 @@
+use super::ids::PulseId;
+use std::collections::HashMap;
+
+/// Remembers when the last subscription event for each object came in, `None` being the server.
+/// An answer to a query is stale if an event for its object arrived after the query went out:
+/// either the object is gone by now, or the query started for that event brings the newer state.
+/// Pulse doesn't reuse indexes, so nothing is ever forgotten while the connection lasts.
+#[derive(Debug, Default)]
+pub(crate) struct Generations {
+    seq: u64,
+    last_event: HashMap<Option<PulseId>, u64>,
+}
+
+impl Generations {
+    /// To be remembered when sending a query, and compared against with `is_fresh` later.
+    pub fn now(&self) -> u64 {
+        self.seq
+    }
+
+    /// Records an event for `id`, returns the generation a query made for it is asked at.
+    pub fn event(&mut self, id: Option<PulseId>) -> u64 {
+        self.seq += 1;
+        self.last_event.insert(id, self.seq);
+        self.seq
+    }
+
+    pub fn is_fresh(&self, id: Option<PulseId>, asked_at: u64) -> bool {
+        self.last_event.get(&id).map_or(true, |&seen| seen <= asked_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::futuristic_pulse::ids::SinkId;
+
+    const SINK: Option<PulseId> = Some(PulseId::Sink(SinkId(1)));
+
+    #[test]
+    fn fresh_without_events() {
+        let generations = Generations::default();
+        assert!(generations.is_fresh(SINK, generations.now()));
+        assert!(generations.is_fresh(None, generations.now()));
+    }
+
+    #[test]
+    fn stale_after_a_later_event() {
+        let mut generations = Generations::default();
+        let asked_at = generations.now();
+        generations.event(SINK);
+        assert!(!generations.is_fresh(SINK, asked_at));
+        assert!(generations.is_fresh(SINK, generations.now()));
+    }
+
+    #[test]
+    fn events_only_touch_their_object() {
+        let mut generations = Generations::default();
+        let asked_at = generations.now();
+        generations.event(None);
+        assert!(generations.is_fresh(SINK, asked_at));
+        assert!(!generations.is_fresh(None, asked_at));
+    }
+}
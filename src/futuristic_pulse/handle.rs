@@ -0,0 +1,144 @@
+use super::commands::{CommandOutput, PulseCommand};
+use super::error::PulseError;
+use super::ids::ModuleId;
+use super::modules::ModuleSpec;
+use super::wakeup::MainloopWaker;
+use super::{init_pulse, PulseAddMessage, PulseMessage};
+use futures::channel::mpsc::{channel, unbounded, Receiver, UnboundedSender};
+use futures::channel::oneshot;
+use futures::channel::oneshot::Canceled;
+use futures::future::{BoxFuture, FutureExt};
+use pulse::error::{Code, PAErr};
+
+/// What the pulse thread can be asked to do.
+#[derive(Debug)]
+pub(crate) enum Request {
+    Command(CommandRequest),
+    List(ListRequest),
+}
+
+/// A command on its way to the pulse thread, with somewhere to put the outcome.
+#[derive(Debug)]
+pub(crate) struct CommandRequest {
+    pub command: PulseCommand,
+    pub reply: Option<oneshot::Sender<CommandOutput>>,
+}
+
+impl CommandRequest {
+    pub fn answer(self, output: CommandOutput) -> PulseCommand {
+        if let Some(reply) = self.reply {
+            let _ = reply.send(output);
+        }
+        self.command
+    }
+}
+
+/// The objects `PulseHandle::list` can fetch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ListKind {
+    Sinks,
+    Sources,
+    SinkInputs,
+    SourceOutputs,
+    Cards,
+    Clients,
+    Modules,
+}
+
+pub type ListReply = Result<Vec<PulseAddMessage<'static>>, PulseError>;
+
+#[derive(Debug)]
+pub(crate) struct ListRequest {
+    pub kind: ListKind,
+    pub reply: oneshot::Sender<ListReply>,
+}
+
+/// Talks to the thread owning a pulse connection. Cheap to clone and fine to send around, every
+/// clone drives the same connection. The thread shuts down once either all handles or the message
+/// receiver are gone, the latter being noticed the next time the server or a handle wakes it up.
+#[derive(Clone, Debug)]
+pub struct PulseHandle {
+    requests: UnboundedSender<Request>,
+    waker: MainloopWaker,
+}
+
+fn closed_is_failure(result: Result<CommandOutput, Canceled>) -> CommandOutput {
+    result.unwrap_or(CommandOutput::Failed)
+}
+
+fn closed_is_error(result: Result<ListReply, Canceled>) -> ListReply {
+    result.unwrap_or_else(|_| Err(PulseError(PAErr::from(Code::ConnectionTerminated))))
+}
+
+impl PulseHandle {
+    /// Starts the pulse thread for `server`, `None` being the default server. Everything it
+    /// learns about the server arrives on the receiver.
+    pub fn spawn(server: Option<String>) -> (PulseHandle, Receiver<PulseMessage<'static>>) {
+        let (requests, requests_recv) = unbounded();
+        let (send, recv) = channel(1024); // TODO channel size
+        let waker = MainloopWaker::default();
+        let thread_waker = waker.clone();
+        std::thread::spawn(move || init_pulse(server, send, requests_recv, thread_waker));
+        (PulseHandle { requests, waker }, recv)
+    }
+
+    // The pulse thread waits in the mainloop, it only looks at the requests once woken up.
+    fn request(&self, request: Request) -> Result<(), Request> {
+        let sent = self.requests.unbounded_send(request).map_err(|err| err.into_inner());
+        self.waker.wake();
+        sent
+    }
+
+    /// Runs `command` on the pulse thread. Resolves to whether the server accepted it, a command
+    /// that never made it there counts as failed.
+    pub fn execute(&self, command: PulseCommand) -> BoxFuture<'static, bool> {
+        self.execute_output(command).map(CommandOutput::success).boxed()
+    }
+
+    /// Like `execute`, for commands that hand something back.
+    pub fn execute_output(&self, command: PulseCommand) -> BoxFuture<'static, CommandOutput> {
+        let (reply, future) = oneshot::channel();
+        let request = CommandRequest { command, reply: Some(reply) };
+        // If this fails the reply sender is dropped with it, which fails the future.
+        let _ = self.request(Request::Command(request));
+        future.map(closed_is_failure).boxed()
+    }
+
+    /// Loads `module`, resolves to its index or `None` if the server refused.
+    pub fn load_module(&self, module: &dyn ModuleSpec) -> BoxFuture<'static, Option<ModuleId>> {
+        self.execute_output(PulseCommand::load_module(module))
+            .map(|output| match output {
+                CommandOutput::ModuleLoaded(module) => Some(module),
+                _ => None,
+            })
+            .boxed()
+    }
+
+    /// Like `execute`, for when only the `MsgCommandResult` is of interest. Hands the command
+    /// back if the pulse thread is gone.
+    pub fn send(&self, command: PulseCommand) -> Result<(), PulseCommand> {
+        let request = CommandRequest { command: command.clone(), reply: None };
+        self.request(Request::Command(request)).map_err(|_| command)
+    }
+
+    /// Fetches the current list of `kind` objects. Asked while connecting it waits for the
+    /// connection to be ready, it fails if the connection gets lost first or is down waiting to
+    /// reconnect.
+    pub fn list(&self, kind: ListKind) -> BoxFuture<'static, ListReply> {
+        let (reply, future) = oneshot::channel();
+        let _ = self.request(Request::List(ListRequest { kind, reply }));
+        future.map(closed_is_error).boxed()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.requests.is_closed()
+    }
+}
+
+impl Drop for PulseHandle {
+    // Might be the last handle, the thread only notices that once it looks at the requests.
+    fn drop(&mut self) {
+        self.requests.disconnect();
+        self.waker.wake();
+    }
+}
@@ -0,0 +1,100 @@
+use libpulse_binding::context::subscribe::Facility;
+
+// Pulse hands out indexes per facility, so sink 3 and sink input 3 are
+// unrelated objects. Keep them apart in the type system.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SinkId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SinkInputId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceOutputId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModuleId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SampleId(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CardId(pub u32);
+
+/// One of the servers we are connected to. Indexes are only unique per server, so across
+/// connections an object is identified by (server, facility, index).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ServerId(pub usize);
+
+/// An index tagged with the facility it belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PulseId {
+    Sink(SinkId),
+    Source(SourceId),
+    SinkInput(SinkInputId),
+    SourceOutput(SourceOutputId),
+    Module(ModuleId),
+    Client(ClientId),
+    SampleCache(SampleId),
+    Card(CardId),
+}
+
+impl PulseId {
+    /// `None` for `Facility::Server`, which has no index space of its own.
+    pub fn new(facility: Facility, index: u32) -> Option<PulseId> {
+        match facility {
+            Facility::Sink => Some(PulseId::Sink(SinkId(index))),
+            Facility::Source => Some(PulseId::Source(SourceId(index))),
+            Facility::SinkInput => Some(PulseId::SinkInput(SinkInputId(index))),
+            Facility::SourceOutput => Some(PulseId::SourceOutput(SourceOutputId(index))),
+            Facility::Module => Some(PulseId::Module(ModuleId(index))),
+            Facility::Client => Some(PulseId::Client(ClientId(index))),
+            Facility::SampleCache => Some(PulseId::SampleCache(SampleId(index))),
+            Facility::Card => Some(PulseId::Card(CardId(index))),
+            Facility::Server => None,
+        }
+    }
+
+    pub fn facility(&self) -> Facility {
+        match self {
+            PulseId::Sink(_) => Facility::Sink,
+            PulseId::Source(_) => Facility::Source,
+            PulseId::SinkInput(_) => Facility::SinkInput,
+            PulseId::SourceOutput(_) => Facility::SourceOutput,
+            PulseId::Module(_) => Facility::Module,
+            PulseId::Client(_) => Facility::Client,
+            PulseId::SampleCache(_) => Facility::SampleCache,
+            PulseId::Card(_) => Facility::Card,
+        }
+    }
+
+    pub fn index(&self) -> u32 {
+        match *self {
+            PulseId::Sink(SinkId(i))
+            | PulseId::Source(SourceId(i))
+            | PulseId::SinkInput(SinkInputId(i))
+            | PulseId::SourceOutput(SourceOutputId(i))
+            | PulseId::Module(ModuleId(i))
+            | PulseId::Client(ClientId(i))
+            | PulseId::SampleCache(SampleId(i))
+            | PulseId::Card(CardId(i)) => i,
+        }
+    }
+}
+
+/// Anything with a volume and a mute switch. Devices can also be addressed by name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectRef {
+    Sink(SinkId),
+    SinkName(String),
+    Source(SourceId),
+    SourceName(String),
+    SinkInput(SinkInputId),
+    SourceOutput(SourceOutputId),
+}
@@ -0,0 +1,440 @@
+use super::error::PulseError;
+use super::ids::PulseId;
+use super::{ConnectionState, PulseAddMessage, PulseMessage};
+use pulse::context::introspect::{
+    CardInfo, CardProfileInfo, ClientInfo, ModuleInfo, ServerInfo, SinkInfo, SinkInputInfo, SinkPortInfo, SourceInfo,
+    SourceOutputInfo, SourcePortInfo,
+};
+use pulse::def::PortAvailable;
+use pulse::proplist::Proplist;
+use pulse::volume::{ChannelVolumes, Volume};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+// Owned copies of what the introspect types tell us, for anything that wants to serialize them
+// or keep them around in a druid state. Volumes are pulse's raw per channel values, 65536 is 100%.
+//
+// The `druid::Data` derives only exist with the `gui` feature. Collections don't have a cheap way
+// to compare, they are compared by value for druid.
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct Port {
+    pub name: String,
+    pub description: String,
+    pub priority: u32,
+    /// `None` if pulse doesn't know.
+    pub available: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct Sink {
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    pub driver: String,
+    pub owner_module: Option<u32>,
+    pub card: Option<u32>,
+    pub monitor_source: u32,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub volume: Vec<u32>,
+    pub base_volume: u32,
+    pub mute: bool,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub ports: Vec<Port>,
+    pub active_port: Option<String>,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct Source {
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    pub driver: String,
+    pub owner_module: Option<u32>,
+    pub card: Option<u32>,
+    /// Set for the monitor source of a sink.
+    pub monitor_of_sink: Option<u32>,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub volume: Vec<u32>,
+    pub base_volume: u32,
+    pub mute: bool,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub ports: Vec<Port>,
+    pub active_port: Option<String>,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct SinkInput {
+    pub index: u32,
+    pub name: String,
+    pub driver: String,
+    pub owner_module: Option<u32>,
+    pub client: Option<u32>,
+    pub sink: u32,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub volume: Vec<u32>,
+    pub mute: bool,
+    pub corked: bool,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct SourceOutput {
+    pub index: u32,
+    pub name: String,
+    pub driver: String,
+    pub owner_module: Option<u32>,
+    pub client: Option<u32>,
+    pub source: u32,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub volume: Vec<u32>,
+    pub mute: bool,
+    pub corked: bool,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct CardProfile {
+    pub name: String,
+    pub description: String,
+    pub n_sinks: u32,
+    pub n_sources: u32,
+    pub priority: u32,
+    pub available: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct Card {
+    pub index: u32,
+    pub name: String,
+    pub driver: String,
+    pub owner_module: Option<u32>,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub profiles: Vec<CardProfile>,
+    pub active_profile: Option<CardProfile>,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct Client {
+    pub index: u32,
+    pub name: String,
+    pub driver: String,
+    pub owner_module: Option<u32>,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct Module {
+    pub index: u32,
+    pub name: String,
+    pub argument: String,
+    /// `None` if the module doesn't keep count.
+    pub n_used: Option<u32>,
+    #[cfg_attr(feature = "gui", data(same_fn = "PartialEq::eq"))]
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "gui", derive(druid::Data))]
+pub struct Server {
+    pub name: String,
+    pub version: String,
+    pub default_sink: String,
+    pub default_source: String,
+}
+
+fn string(value: &Option<Cow<str>>) -> String {
+    value.as_ref().map(|s| s.to_string()).unwrap_or_default()
+}
+
+pub fn levels(volume: &ChannelVolumes) -> Vec<u32> {
+    volume.get().iter().map(|v| v.0).collect()
+}
+
+/// The other way around, for sending volumes back to pulse.
+pub fn channel_volumes(volume: &[u32]) -> ChannelVolumes {
+    let mut channel_volumes = ChannelVolumes::default();
+    channel_volumes.set_len(volume.len() as u8);
+    for (channel, value) in channel_volumes.get_mut().iter_mut().zip(volume) {
+        *channel = Volume(*value);
+    }
+    channel_volumes
+}
+
+/// The loudest channel, 1.0 being 100%. What pavucontrol's slider shows as well.
+pub fn volume_fraction(volume: &ChannelVolumes) -> f64 {
+    volume.max().0 as f64 / Volume::NORMAL.0 as f64
+}
+
+/// Sets the loudest channel to `fraction`, the others follow so the balance is kept. Clamped to
+/// what pulse accepts.
+pub fn set_volume_fraction(volume: &mut ChannelVolumes, fraction: f64) {
+    let value = (fraction.max(0.0) * Volume::NORMAL.0 as f64).min(Volume::MAX.0 as f64);
+    volume.scale(Volume(value as u32));
+}
+
+fn properties(proplist: &Proplist) -> BTreeMap<String, String> {
+    proplist
+        .iter()
+        .filter_map(|key| proplist.get_str(&key).map(|value| (key, value)))
+        .collect()
+}
+
+fn available(available: PortAvailable) -> Option<bool> {
+    match available {
+        PortAvailable::Yes => Some(true),
+        PortAvailable::No => Some(false),
+        PortAvailable::Unknown => None,
+    }
+}
+
+impl<'a> From<&SinkPortInfo<'a>> for Port {
+    fn from(port: &SinkPortInfo<'a>) -> Self {
+        Port {
+            name: string(&port.name),
+            description: string(&port.description),
+            priority: port.priority,
+            available: available(port.available),
+        }
+    }
+}
+
+impl<'a> From<&SourcePortInfo<'a>> for Port {
+    fn from(port: &SourcePortInfo<'a>) -> Self {
+        Port {
+            name: string(&port.name),
+            description: string(&port.description),
+            priority: port.priority,
+            available: available(port.available),
+        }
+    }
+}
+
+impl<'a> From<&SinkInfo<'a>> for Sink {
+    fn from(info: &SinkInfo<'a>) -> Self {
+        Sink {
+            index: info.index,
+            name: string(&info.name),
+            description: string(&info.description),
+            driver: string(&info.driver),
+            owner_module: info.owner_module,
+            card: info.card,
+            monitor_source: info.monitor_source,
+            volume: levels(&info.volume),
+            base_volume: info.base_volume.0,
+            mute: info.mute,
+            ports: info.ports.iter().map(Port::from).collect(),
+            active_port: info.active_port.as_ref().map(|port| string(&port.name)),
+            properties: properties(&info.proplist),
+        }
+    }
+}
+
+impl<'a> From<&SourceInfo<'a>> for Source {
+    fn from(info: &SourceInfo<'a>) -> Self {
+        Source {
+            index: info.index,
+            name: string(&info.name),
+            description: string(&info.description),
+            driver: string(&info.driver),
+            owner_module: info.owner_module,
+            card: info.card,
+            monitor_of_sink: info.monitor_of_sink,
+            volume: levels(&info.volume),
+            base_volume: info.base_volume.0,
+            mute: info.mute,
+            ports: info.ports.iter().map(Port::from).collect(),
+            active_port: info.active_port.as_ref().map(|port| string(&port.name)),
+            properties: properties(&info.proplist),
+        }
+    }
+}
+
+impl<'a> From<&SinkInputInfo<'a>> for SinkInput {
+    fn from(info: &SinkInputInfo<'a>) -> Self {
+        SinkInput {
+            index: info.index,
+            name: string(&info.name),
+            driver: string(&info.driver),
+            owner_module: info.owner_module,
+            client: info.client,
+            sink: info.sink,
+            volume: levels(&info.volume),
+            mute: info.mute,
+            corked: info.corked,
+            properties: properties(&info.proplist),
+        }
+    }
+}
+
+impl<'a> From<&SourceOutputInfo<'a>> for SourceOutput {
+    fn from(info: &SourceOutputInfo<'a>) -> Self {
+        SourceOutput {
+            index: info.index,
+            name: string(&info.name),
+            driver: string(&info.driver),
+            owner_module: info.owner_module,
+            client: info.client,
+            source: info.source,
+            volume: levels(&info.volume),
+            mute: info.mute,
+            corked: info.corked,
+            properties: properties(&info.proplist),
+        }
+    }
+}
+
+impl<'a> From<&CardProfileInfo<'a>> for CardProfile {
+    fn from(profile: &CardProfileInfo<'a>) -> Self {
+        CardProfile {
+            name: string(&profile.name),
+            description: string(&profile.description),
+            n_sinks: profile.n_sinks,
+            n_sources: profile.n_sources,
+            priority: profile.priority,
+            available: profile.available,
+        }
+    }
+}
+
+impl<'a> From<&CardInfo<'a>> for Card {
+    fn from(info: &CardInfo<'a>) -> Self {
+        Card {
+            index: info.index,
+            name: string(&info.name),
+            driver: string(&info.driver),
+            owner_module: info.owner_module,
+            profiles: info.profiles.iter().map(CardProfile::from).collect(),
+            active_profile: info.active_profile.as_ref().map(|profile| CardProfile::from(&**profile)),
+            properties: properties(&info.proplist),
+        }
+    }
+}
+
+impl<'a> From<&ClientInfo<'a>> for Client {
+    fn from(info: &ClientInfo<'a>) -> Self {
+        Client {
+            index: info.index,
+            name: string(&info.name),
+            driver: string(&info.driver),
+            owner_module: info.owner_module,
+            properties: properties(&info.proplist),
+        }
+    }
+}
+
+impl<'a> From<&ModuleInfo<'a>> for Module {
+    fn from(info: &ModuleInfo<'a>) -> Self {
+        Module {
+            index: info.index,
+            name: string(&info.name),
+            argument: string(&info.argument),
+            n_used: info.n_used,
+            properties: properties(&info.proplist),
+        }
+    }
+}
+
+impl<'a> From<&ServerInfo<'a>> for Server {
+    fn from(info: &ServerInfo<'a>) -> Self {
+        Server {
+            name: string(&info.server_name),
+            version: string(&info.server_version),
+            default_sink: string(&info.default_sink_name),
+            default_source: string(&info.default_source_name),
+        }
+    }
+}
+
+/// A `PulseMessage` as one line of JSON. Objects are sent whole on every change.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    Sink(Sink),
+    Source(Source),
+    SinkInput(SinkInput),
+    SourceOutput(SourceOutput),
+    Card(Card),
+    Client(Client),
+    Module(Module),
+    Removed {
+        facility: String,
+        index: u32,
+    },
+    Server(Server),
+    Connection {
+        state: String,
+        error: Option<String>,
+    },
+    Error {
+        error: String,
+    },
+    /// Everything that existed on connecting has been printed.
+    SyncComplete,
+}
+
+/// The tag the object's own events carry, so removals match up with them.
+pub fn facility_tag(id: &PulseId) -> &'static str {
+    match id {
+        PulseId::Sink(_) => "sink",
+        PulseId::Source(_) => "source",
+        PulseId::SinkInput(_) => "sink-input",
+        PulseId::SourceOutput(_) => "source-output",
+        PulseId::Module(_) => "module",
+        PulseId::Client(_) => "client",
+        PulseId::SampleCache(_) => "sample-cache",
+        PulseId::Card(_) => "card",
+    }
+}
+
+impl Event {
+    /// `None` for peaks and command results, they only make sense to whoever asked for them.
+    pub fn from_message(message: &PulseMessage) -> Option<Event> {
+        match message {
+            PulseMessage::MsgAdd { msg, .. } => match msg {
+                PulseAddMessage::MsgSink(info) => Some(Event::Sink(info.into())),
+                PulseAddMessage::MsgSource(info) => Some(Event::Source(info.into())),
+                PulseAddMessage::MsgSinkInput(info) => Some(Event::SinkInput(info.into())),
+                PulseAddMessage::MsgSourceOutput(info) => Some(Event::SourceOutput(info.into())),
+                PulseAddMessage::MsgCard(info) => Some(Event::Card(info.into())),
+                PulseAddMessage::MsgClient(info) => Some(Event::Client(info.into())),
+                PulseAddMessage::MsgModule(info) => Some(Event::Module(info.into())),
+            },
+            PulseMessage::MsgDel { id } => Some(Event::Removed {
+                facility: facility_tag(id).to_string(),
+                index: id.index(),
+            }),
+            PulseMessage::MsgServer { info } => Some(Event::Server(info.into())),
+            PulseMessage::MsgConnection { state } => Some(match state {
+                ConnectionState::Connecting => Event::Connection { state: "connecting".to_string(), error: None },
+                ConnectionState::Ready => Event::Connection { state: "ready".to_string(), error: None },
+                ConnectionState::Disconnected { error, .. } => Event::Connection {
+                    state: "disconnected".to_string(),
+                    error: error.as_ref().map(PulseError::to_string),
+                },
+            }),
+            PulseMessage::MsgError { error } => Some(Event::Error { error: error.to_string() }),
+            PulseMessage::MsgSyncComplete => Some(Event::SyncComplete),
+            PulseMessage::MsgCommandResult { .. } | PulseMessage::MsgPeaks { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+// Typed argument builders for the modules we use to set up virtual routing.
+// Everything not set is left to the module's default.
+
+pub trait ModuleSpec {
+    fn name(&self) -> &str;
+    fn arguments(&self) -> String;
+}
+
+/// Space separated `key=value` pairs, quoted the way pa_modargs expects.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleArguments(Vec<String>);
+
+impl ModuleArguments {
+    pub fn new() -> Self {
+        ModuleArguments(Vec::new())
+    }
+
+    pub fn push(&mut self, key: &str, value: &str) -> &mut Self {
+        self.0.push(format!("{}={}", key, quote(value, '"')));
+        self
+    }
+
+    pub fn push_opt<T: ToString>(&mut self, key: &str, value: &Option<T>) -> &mut Self {
+        if let Some(value) = value {
+            self.push(key, &value.to_string());
+        }
+        self
+    }
+
+    /// `device.description` is a proplist nested inside a single argument, so it gets its own quoting level.
+    pub fn push_description(&mut self, key: &str, description: &Option<String>) -> &mut Self {
+        if let Some(description) = description {
+            self.push(key, &format!("device.description={}", quote(description, '\'')));
+        }
+        self
+    }
+
+    pub fn build(&self) -> String {
+        self.0.join(" ")
+    }
+}
+
+fn quote(value: &str, quote: char) -> String {
+    let needs_quoting = |c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\';
+    if !value.is_empty() && !value.contains(needs_quoting) {
+        return value.to_string();
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push(quote);
+    for c in value.chars() {
+        if c == quote || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push(quote);
+    quoted
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NullSink {
+    pub sink_name: Option<String>,
+    pub description: Option<String>,
+    pub channels: Option<u8>,
+    pub rate: Option<u32>,
+}
+
+impl NullSink {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn sink_name(mut self, name: &str) -> Self {
+        self.sink_name = Some(name.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn channels(mut self, channels: u8) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+
+    pub fn rate(mut self, rate: u32) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+}
+
+impl ModuleSpec for NullSink {
+    fn name(&self) -> &str {
+        "module-null-sink"
+    }
+
+    fn arguments(&self) -> String {
+        ModuleArguments::new()
+            .push_opt("sink_name", &self.sink_name)
+            .push_description("sink_properties", &self.description)
+            .push_opt("channels", &self.channels)
+            .push_opt("rate", &self.rate)
+            .build()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Loopback {
+    pub source: Option<String>,
+    pub sink: Option<String>,
+    pub latency_msec: Option<u32>,
+    pub source_dont_move: Option<bool>,
+    pub sink_dont_move: Option<bool>,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    pub fn sink(mut self, sink: &str) -> Self {
+        self.sink = Some(sink.to_string());
+        self
+    }
+
+    pub fn latency_msec(mut self, latency: u32) -> Self {
+        self.latency_msec = Some(latency);
+        self
+    }
+
+    pub fn source_dont_move(mut self, dont_move: bool) -> Self {
+        self.source_dont_move = Some(dont_move);
+        self
+    }
+
+    pub fn sink_dont_move(mut self, dont_move: bool) -> Self {
+        self.sink_dont_move = Some(dont_move);
+        self
+    }
+}
+
+impl ModuleSpec for Loopback {
+    fn name(&self) -> &str {
+        "module-loopback"
+    }
+
+    fn arguments(&self) -> String {
+        ModuleArguments::new()
+            .push_opt("source", &self.source)
+            .push_opt("sink", &self.sink)
+            .push_opt("latency_msec", &self.latency_msec)
+            .push_opt("source_dont_move", &self.source_dont_move)
+            .push_opt("sink_dont_move", &self.sink_dont_move)
+            .build()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CombineSink {
+    pub sink_name: Option<String>,
+    pub description: Option<String>,
+    pub slaves: Vec<String>,
+}
+
+impl CombineSink {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn sink_name(mut self, name: &str) -> Self {
+        self.sink_name = Some(name.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn slave(mut self, sink: &str) -> Self {
+        self.slaves.push(sink.to_string());
+        self
+    }
+}
+
+impl ModuleSpec for CombineSink {
+    fn name(&self) -> &str {
+        "module-combine-sink"
+    }
+
+    fn arguments(&self) -> String {
+        let mut arguments = ModuleArguments::new();
+        arguments
+            .push_opt("sink_name", &self.sink_name)
+            .push_description("sink_properties", &self.description);
+        if !self.slaves.is_empty() {
+            arguments.push("slaves", &self.slaves.join(","));
+        }
+        arguments.build()
+    }
+}
+
+/// Escape hatch for modules without a typed builder.
+#[derive(Clone, Debug)]
+pub struct RawModule {
+    pub name: String,
+    pub arguments: String,
+}
+
+impl ModuleSpec for RawModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arguments(&self) -> String {
+        self.arguments.clone()
+    }
+}
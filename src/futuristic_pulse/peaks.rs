@@ -0,0 +1,209 @@
+use super::ids::{ObjectRef, SinkId, SinkInputId, SourceId};
+use super::{PulseAddMessage, PulseMessage};
+use futures::channel::mpsc::Sender;
+use pulse::context::Context;
+use pulse::def::BufferAttr;
+use pulse::proplist::Proplist;
+use pulse::sample::Spec;
+use pulse::stream::{flags, PeekResult, State, Stream};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::rc::Rc;
+use std::time::Duration;
+
+// With PEAK_DETECT every sample is the peak over 1/PEAK_RATE seconds.
+const PEAK_RATE: u32 = 25;
+/// How often collected peaks are sent on as one batch.
+pub(crate) const PEAK_INTERVAL: Duration = Duration::from_millis(50);
+/// Set on the meters' record streams, so they can be kept out of the stream lists.
+pub(crate) const PEAK_METER_PROPERTY: &str = "pulsecontrol.peak-meter";
+
+/// Whether this is the record stream behind one of our meters.
+pub(crate) fn is_peak_meter(msg: &PulseAddMessage) -> bool {
+    match msg {
+        PulseAddMessage::MsgSourceOutput(info) => info.proplist.get_str(PEAK_METER_PROPERTY).is_some(),
+        _ => false,
+    }
+}
+
+/// What a meter records from. Sinks and sink inputs are recorded through the sink's monitor source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PeakSource {
+    Sink { sink: SinkId, monitor_source: SourceId },
+    Source(SourceId),
+    SinkInput { sink_input: SinkInputId, monitor_source: SourceId },
+}
+
+impl PeakSource {
+    pub fn target(&self) -> ObjectRef {
+        match *self {
+            PeakSource::Sink { sink, .. } => ObjectRef::Sink(sink),
+            PeakSource::Source(source) => ObjectRef::Source(source),
+            PeakSource::SinkInput { sink_input, .. } => ObjectRef::SinkInput(sink_input),
+        }
+    }
+
+    fn device(&self) -> SourceId {
+        match *self {
+            PeakSource::Sink { monitor_source, .. } => monitor_source,
+            PeakSource::Source(source) => source,
+            PeakSource::SinkInput { monitor_source, .. } => monitor_source,
+        }
+    }
+}
+
+/// The record streams behind the meters. Lives on the pulse thread.
+pub(crate) struct PeakMeters {
+    streams: HashMap<PeakSource, Rc<RefCell<Stream>>>,
+    pending: Rc<RefCell<HashMap<PeakSource, f32>>>,
+    // Streams the server ended, e.g. because the sink input finished. Evicted from `streams` on
+    // the next call, not from within their own state callback.
+    dead: Rc<RefCell<HashSet<PeakSource>>>,
+    sender: Sender<PulseMessage<'static>>,
+}
+
+impl PeakMeters {
+    pub fn new(sender: Sender<PulseMessage<'static>>) -> Self {
+        PeakMeters {
+            streams: HashMap::new(),
+            pending: Rc::new(RefCell::new(HashMap::new())),
+            dead: Rc::new(RefCell::new(HashSet::new())),
+            sender,
+        }
+    }
+
+    pub fn start(&mut self, context: &mut Context, source: PeakSource) -> bool {
+        self.evict_dead();
+        if self.streams.contains_key(&source) {
+            return true;
+        }
+        let spec = Spec {
+            format: pulse::sample::SAMPLE_FLOAT32NE,
+            channels: 1,
+            rate: PEAK_RATE,
+        };
+        let mut proplist = match Proplist::new() {
+            Some(proplist) => proplist,
+            None => return false,
+        };
+        if proplist.set_str(PEAK_METER_PROPERTY, "1").is_err() {
+            return false;
+        }
+        let stream = match Stream::new_with_proplist(context, "Peak detect", &spec, None, &mut proplist) {
+            Some(stream) => Rc::new(RefCell::new(stream)),
+            None => {
+                eprintln!("Failed to create peak stream for {:?}", source);
+                return false;
+            }
+        };
+        let attr = BufferAttr {
+            maxlength: std::u32::MAX,
+            tlength: 0,
+            prebuf: 0,
+            minreq: 0,
+            fragsize: size_of::<f32>() as u32,
+        };
+        let flags = flags::DONT_MOVE | flags::PEAK_DETECT | flags::ADJUST_LATENCY | flags::DONT_INHIBIT_AUTO_SUSPEND;
+
+        let weak = Rc::downgrade(&stream);
+        let dead = self.dead.clone();
+        let mut s = stream.borrow_mut();
+        s.set_state_callback(Some(Box::new(move || {
+            let stream = match weak.upgrade() {
+                Some(stream) => stream,
+                None => return,
+            };
+            // Borrowed means start or stop are running, they take care of it themselves.
+            let state = match stream.try_borrow() {
+                Ok(stream) => stream.get_state(),
+                Err(_) => return,
+            };
+            if let State::Failed | State::Terminated = state {
+                dead.borrow_mut().insert(source);
+            }
+        })));
+
+        let weak = Rc::downgrade(&stream);
+        let pending = self.pending.clone();
+        s.set_read_callback(Some(Box::new(move |_length| {
+            let stream = match weak.upgrade() {
+                Some(stream) => stream,
+                None => return,
+            };
+            let mut stream = stream.borrow_mut();
+            let peak = match stream.peek() {
+                Ok(PeekResult::Data(data)) => Some(
+                    data.chunks_exact(size_of::<f32>())
+                        .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+                        .fold(0.0, f32::max),
+                ),
+                Ok(PeekResult::Hole(_)) => None,
+                Ok(PeekResult::Empty) | Err(_) => return,
+            };
+            let _ = stream.discard();
+            if let Some(peak) = peak {
+                let mut pending = pending.borrow_mut();
+                let entry = pending.entry(source).or_insert(0.0);
+                *entry = entry.max(peak);
+            }
+        })));
+        if let PeakSource::SinkInput { sink_input, .. } = source {
+            if let Err(err) = s.set_monitor_stream(sink_input.0) {
+                eprintln!("Failed to monitor {:?}: {:?}", source, err);
+                return false;
+            }
+        }
+        let device = source.device().0.to_string();
+        if let Err(err) = s.connect_record(Some(&device), Some(&attr), flags) {
+            eprintln!("Failed to record {:?}: {:?}", source, err);
+            return false;
+        }
+        drop(s);
+        self.streams.insert(source, stream);
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Meters the server already ended, or that never started, are fine to stop as well.
+    pub fn stop(&mut self, source: PeakSource) {
+        self.pending.borrow_mut().remove(&source);
+        self.dead.borrow_mut().remove(&source);
+        if let Some(stream) = self.streams.remove(&source) {
+            let mut stream = stream.borrow_mut();
+            stream.set_state_callback(None);
+            stream.set_read_callback(None);
+            // Disconnecting a stream that isn't ready anymore only gets a bad state error.
+            if stream.get_state() == State::Ready {
+                if let Err(err) = stream.disconnect() {
+                    eprintln!("Failed to disconnect the meter for {:?}: {:?}", source, err);
+                }
+            }
+        }
+    }
+
+    fn evict_dead(&mut self) {
+        for source in self.dead.borrow_mut().drain() {
+            self.pending.borrow_mut().remove(&source);
+            if let Some(stream) = self.streams.remove(&source) {
+                let mut stream = stream.borrow_mut();
+                stream.set_state_callback(None);
+                stream.set_read_callback(None);
+            }
+        }
+    }
+
+    /// Sends everything collected since the last flush as one message.
+    pub fn flush(&mut self) {
+        self.evict_dead();
+        let peaks: Vec<(PeakSource, f32)> = self.pending.borrow_mut().drain().collect();
+        if peaks.is_empty() {
+            return;
+        }
+        // Meters are lossy by nature, a batch that doesn't fit is superseded by the next one.
+        let _ = self.sender.try_send(PulseMessage::MsgPeaks { peaks });
+    }
+}
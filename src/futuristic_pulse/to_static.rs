@@ -4,12 +4,13 @@ use libpulse_binding::time::MicroSeconds;
 use libpulse_binding::channelmap::Map;
 use libpulse_binding::{
     context::introspect::{
+        CardInfo, CardPortInfo, CardProfileInfo, ClientInfo, ModuleInfo, ServerInfo,
         SinkInfo, SinkInputInfo, SinkPortInfo, SourceInfo, SourceOutputInfo, SourcePortInfo,
     }, sample::Spec, volume::ChannelVolumes, proplist::Proplist, def::{SinkState, SourceState},
 };
 use std::{borrow::Cow, boxed::Box};
 
-pub trait ToStatic {
+pub(crate) trait ToStatic {
     type Static;
     fn to_static(&self) -> Self::Static;
 }
@@ -40,6 +41,38 @@ impl<'a> ToStatic for SinkPortInfo<'a> {
     }
 }
 
+impl<'a> ToStatic for CardProfileInfo<'a> {
+    type Static = CardProfileInfo<'static>;
+
+    fn to_static(&self) -> Self::Static {
+        CardProfileInfo {
+            name: self.name.to_static(),
+            description: self.description.to_static(),
+            n_sinks: self.n_sinks,
+            n_sources: self.n_sources,
+            priority: self.priority,
+            available: self.available,
+        }
+    }
+}
+
+impl<'a> ToStatic for CardPortInfo<'a> {
+    type Static = CardPortInfo<'static>;
+
+    fn to_static(&self) -> Self::Static {
+        CardPortInfo {
+            name: self.name.to_static(),
+            description: self.description.to_static(),
+            priority: self.priority,
+            available: self.available.clone(),
+            direction: self.direction.clone(),
+            proplist: self.proplist.clone(),
+            latency_offset: self.latency_offset,
+            profiles: self.profiles.to_static(),
+        }
+    }
+}
+
 impl<'a> ToStatic for Cow<'a, str> {
     type Static = Cow<'static, str>;
 
@@ -287,3 +320,62 @@ impl<'a> ToStatic for SinkInputInfo<'a> {
         }
     }
 }
+
+impl<'a> ToStatic for CardInfo<'a> {
+    type Static = CardInfo<'static>;
+    fn to_static(&self) -> Self::Static {
+        CardInfo {
+            index: self.index.to_static(),
+            name: self.name.to_static(),
+            owner_module: self.owner_module.to_static(),
+            driver: self.driver.to_static(),
+            proplist: self.proplist.to_static(),
+            ports: self.ports.to_static(),
+            profiles: self.profiles.to_static(),
+            active_profile: self.active_profile.to_static(),
+        }
+    }
+}
+
+impl<'a> ToStatic for ClientInfo<'a> {
+    type Static = ClientInfo<'static>;
+    fn to_static(&self) -> Self::Static {
+        ClientInfo {
+            index: self.index.to_static(),
+            name: self.name.to_static(),
+            owner_module: self.owner_module.to_static(),
+            driver: self.driver.to_static(),
+            proplist: self.proplist.to_static(),
+        }
+    }
+}
+
+impl<'a> ToStatic for ModuleInfo<'a> {
+    type Static = ModuleInfo<'static>;
+    fn to_static(&self) -> Self::Static {
+        ModuleInfo {
+            index: self.index.to_static(),
+            name: self.name.to_static(),
+            argument: self.argument.to_static(),
+            n_used: self.n_used.to_static(),
+            proplist: self.proplist.to_static(),
+        }
+    }
+}
+
+impl<'a> ToStatic for ServerInfo<'a> {
+    type Static = ServerInfo<'static>;
+    fn to_static(&self) -> Self::Static {
+        ServerInfo {
+            user_name: self.user_name.to_static(),
+            host_name: self.host_name.to_static(),
+            server_version: self.server_version.to_static(),
+            server_name: self.server_name.to_static(),
+            sample_spec: self.sample_spec.to_static(),
+            default_sink_name: self.default_sink_name.to_static(),
+            default_source_name: self.default_source_name.to_static(),
+            cookie: self.cookie.to_static(),
+            channel_map: self.channel_map.to_static(),
+        }
+    }
+}
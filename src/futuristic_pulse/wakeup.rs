@@ -0,0 +1,94 @@
+use futures::executor::LocalSpawner;
+use futures::future::{FutureExt, LocalBoxFuture};
+use futures::task::{waker, ArcWake, Context, LocalSpawnExt, Poll, SpawnError, Waker};
+use pulse::mainloop::api::Mainloop as MainloopTrait;
+use pulse::mainloop::standard::Mainloop;
+use pulse_sys::mainloop::standard::{pa_mainloop, pa_mainloop_wakeup};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+// pa_mainloop_wakeup is the one call that is fine from other threads.
+#[derive(Debug)]
+struct RawMainloop(*mut pa_mainloop);
+
+unsafe impl Send for RawMainloop {}
+
+/// Interrupts the pulse thread's mainloop while it waits for the server, so requests get picked
+/// up right away. Wakes whichever mainloop is attached at the moment, with none attached there is
+/// nothing waiting to be woken.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MainloopWaker {
+    mainloop: Arc<Mutex<Option<RawMainloop>>>,
+}
+
+impl MainloopWaker {
+    pub fn wake(&self) {
+        if let Some(RawMainloop(mainloop)) = *self.mainloop.lock().unwrap() {
+            unsafe { pa_mainloop_wakeup(mainloop) };
+        }
+    }
+
+    /// Points the waker at `mainloop` until the guard is dropped, which has to happen before the
+    /// mainloop goes away.
+    pub fn attach(&self, mainloop: &Mainloop) -> Attached {
+        *self.mainloop.lock().unwrap() = Some(RawMainloop(mainloop.inner().get_ptr()));
+        Attached { waker: self.clone() }
+    }
+}
+
+pub(crate) struct Attached {
+    waker: MainloopWaker,
+}
+
+impl Drop for Attached {
+    fn drop(&mut self) {
+        *self.waker.mainloop.lock().unwrap() = None;
+    }
+}
+
+/// Spawns onto the pulse thread's pool. The tasks wake the mainloop along with the pool, otherwise
+/// a task woken from another thread, e.g. by the receiver making room in the channel, would wait
+/// for the server to do something before it gets polled again.
+#[derive(Clone)]
+pub(crate) struct WakingSpawner {
+    spawner: LocalSpawner,
+    waker: MainloopWaker,
+}
+
+impl WakingSpawner {
+    pub fn new(spawner: LocalSpawner, waker: MainloopWaker) -> Self {
+        WakingSpawner { spawner, waker }
+    }
+
+    pub fn spawn_local(&self, future: impl Future<Output = ()> + 'static) -> Result<(), SpawnError> {
+        let waker = self.waker.clone();
+        self.spawner.spawn_local(WakesMainloop { future: future.boxed_local(), waker })
+    }
+}
+
+struct WakesMainloop {
+    future: LocalBoxFuture<'static, ()>,
+    waker: MainloopWaker,
+}
+
+impl Future for WakesMainloop {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let both = waker(Arc::new(WakeBoth { task: cx.waker().clone(), mainloop: self.waker.clone() }));
+        self.future.poll_unpin(&mut Context::from_waker(&both))
+    }
+}
+
+struct WakeBoth {
+    task: Waker,
+    mainloop: MainloopWaker,
+}
+
+impl ArcWake for WakeBoth {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.task.wake_by_ref();
+        arc_self.mainloop.wake();
+    }
+}
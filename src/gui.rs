@@ -0,0 +1,917 @@
+// Copyright 2019 The Druid Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use druid::widget::Controller;
+use druid::widget::ListIter;
+use pulsecontrol::PulseAddMessage::*;
+use druid::Color;
+use druid::WidgetExt;
+use druid::widget::List;
+use druid::widget::Scroll;
+use pulsecontrol::{ConnectionState, PeakSource, PulseCommand, PulseHandle, PulseMessage};
+use pulsecontrol::{NullSink, RawModule};
+use pulsecontrol::{CardId, ClientId, ModuleId, ObjectRef, PulseId, ServerId, SinkId, SourceId, SinkInputId, SourceOutputId};
+use druid::{Env, Key, Selector};
+use druid::{widget::{Button, Checkbox, EnvScope, Flex, Label, CrossAxisAlignment, ProgressBar, Slider, TextBox}, ExtEventSink};
+use druid::{AppLauncher, Widget, WindowDesc, Data, Lens, UnitPoint, lens::self, LensExt};
+use futures::stream::{select_all, StreamExt};
+use tokio::spawn;
+use im;
+use pulse::proplist::properties;
+use pulsecontrol::{channel_volumes, levels, set_volume_fraction, volume_fraction, Card, Client, Module, Port, Server, Sink, SinkInput, Source, SourceOutput};
+use crate::dropdown::Dropdown;
+use crate::config::Config;
+use std::collections::{BTreeMap, HashSet};
+
+// Everything on these selectors is tagged with the server it belongs to, every server section
+// picks out its own.
+const PULSE_CHANGES: Selector<(ServerId, PulseMessage)> = Selector::new("pulsecontrol.pulse-changes");
+const PULSE_COMMAND: Selector<(ServerId, PulseCommand)> = Selector::new("pulsecontrol.pulse-command");
+const DRAG_START: Selector<(ServerId, ObjectRef)> = Selector::new("pulsecontrol.drag-start");
+const DROP: Selector<(ServerId, ObjectRef)> = Selector::new("pulsecontrol.drop");
+
+// Set for every server section, so rows know where their commands go.
+const SERVER: Key<u64> = Key::new("pulsecontrol.server");
+
+fn server_of(env: &Env) -> ServerId {
+    ServerId(env.get(SERVER) as usize)
+}
+
+/// Opens the window, needs to run within the tokio runtime.
+pub fn run(config: Config) {
+    let connections = config.connections();
+    let mut pulse_messages = Vec::new();
+    let mut servers = im::Vector::new();
+    let mut handles = Vec::new();
+    for (id, server) in connections.into_iter().enumerate() {
+        let id = ServerId(id);
+        servers.push_back(PulseState::new(id, server.clone().unwrap_or_else(|| "Default server".to_string())));
+        let (handle, recv) = PulseHandle::spawn(server);
+        handles.push(handle);
+        pulse_messages.push(recv.map(move |pm| (id, pm)));
+    }
+
+    let launcher = AppLauncher::with_window(WindowDesc::new(move || build_ui(handles)).title("PulseControl"));
+    let event_sink = launcher.get_external_handle();
+
+    // pulse_stream.for_each(|pm| async move { eprintln!("Got message: {:?}", pm)}).await;
+
+    let mut messages = select_all(pulse_messages);
+    spawn(async move {
+        while let Some(pm) = messages.next().await {
+            match event_sink.submit_command(PULSE_CHANGES, pm, None) {
+                Err(err) => eprintln!("Error: {:?}", err),
+                Ok(()) => (),
+            };
+        }
+    });
+
+    eprintln!("Launch");
+    launcher.launch(AppState { servers }).expect("launch failed");
+}
+
+#[derive(Clone, Lens, Data, Debug)]
+struct AppState {
+    // Fixed at startup, one per configured server in order of their ServerId.
+    servers: im::Vector<PulseState>,
+}
+
+fn build_ui(handles: Vec<PulseHandle>) -> impl Widget<AppState> {
+    let mut root = Flex::column();
+    for (id, pulse) in handles.into_iter().enumerate() {
+        let section = EnvScope::new(
+            move |env: &mut Env, _data: &PulseState| env.set(SERVER, id as u64),
+            build_server(ServerId(id), pulse),
+        );
+        root.add_flex_child(section.lens(AppState::servers.index(id)), 1.0);
+    }
+    root
+}
+
+fn build_server(server: ServerId, pulse: PulseHandle) -> impl Widget<PulseState> {
+    let mut root = Flex::column();
+    root.add_child(
+        Label::new(|d: &PulseState, _env: &_| d.name.clone())
+            .padding(5.0)
+            .align_left(),
+    );
+    let mut lists: Flex<PulseState> = Flex::row().cross_axis_alignment(CrossAxisAlignment::Start);
+
+    lists.add_flex_child(
+        Scroll::new(List::new(|| {
+            Flex::column()
+                .cross_axis_alignment(CrossAxisAlignment::Start)
+                .with_child(
+                    Flex::row()
+                        .with_child(
+                            Label::new(|item: &SinkRow, _env: &_| {
+                                let name = display_name(&[&item.sink.description, &item.sink.name]);
+                                if item.default {
+                                    format!("{} (default)", name)
+                                } else {
+                                    name.to_string()
+                                }
+                            })
+                            .align_vertical(UnitPoint::LEFT),
+                        )
+                        .with_flex_spacer(1.0)
+                        .with_child(peak_meter())
+                        .with_child(volume_slider())
+                        .with_child(mute_toggle())
+                        .with_child(Button::new("Make default").on_click(|ctx, item: &mut SinkRow, env| {
+                            let command = PulseCommand::SetDefaultSink(item.sink.name.clone());
+                            ctx.submit_command(PULSE_COMMAND.with((server_of(env), command)), None);
+                        })),
+                )
+                .with_child(port_dropdown())
+                .padding(10.0)
+                .background(Color::rgb(0.5, 0.0, 0.5))
+                .controller(RowCommands)
+                .controller(DropTarget)
+        }))
+        .vertical()
+        .lens(lens::Id.map(
+            sink_rows,
+            |d: &mut PulseState, x: im::Vector<SinkRow>| {
+                // If shared data was changed reflect the changes in our AppData
+                for row in x {
+                    d.sinks.insert(SinkId(row.sink.index), row.sink);
+                }
+            },
+        )),
+        1.0,
+    );
+
+    lists.add_flex_child(
+        Scroll::new(List::new(|| {
+            Flex::column()
+                .cross_axis_alignment(CrossAxisAlignment::Start)
+                .with_child(
+                    Flex::row()
+                        .with_child(
+                            Label::new(|item: &SourceRow, _env: &_| {
+                                let name = display_name(&[&item.source.description, &item.source.name]);
+                                if item.default {
+                                    format!("{} (default)", name)
+                                } else {
+                                    name.to_string()
+                                }
+                            })
+                            .align_vertical(UnitPoint::LEFT),
+                        )
+                        .with_flex_spacer(1.0)
+                        .with_child(peak_meter())
+                        .with_child(volume_slider())
+                        .with_child(mute_toggle())
+                        .with_child(Button::new("Make default").on_click(|ctx, item: &mut SourceRow, env| {
+                            let command = PulseCommand::SetDefaultSource(item.source.name.clone());
+                            ctx.submit_command(PULSE_COMMAND.with((server_of(env), command)), None);
+                        })),
+                )
+                .with_child(port_dropdown())
+                .padding(10.0)
+                .background(Color::rgb(0.5, 0.2, 0.0))
+                .controller(RowCommands)
+                .controller(DropTarget)
+        }))
+        .vertical()
+        .lens(lens::Id.map(
+            source_rows,
+            |d: &mut PulseState, x: im::Vector<SourceRow>| {
+                for row in x {
+                    d.sources.insert(SourceId(row.source.index), row.source);
+                }
+            },
+        )),
+        1.0,
+    );
+
+    lists.add_flex_child(
+        Scroll::new(List::new(|| {
+            Flex::row()
+                .with_child(profile_dropdown().align_vertical(UnitPoint::LEFT))
+                .with_flex_spacer(1.0)
+                .padding(10.0)
+                .background(Color::rgb(0.0, 0.3, 0.5))
+        }))
+        .vertical()
+        .lens(lens::Id.map(
+            |d: &PulseState| d.cards.values().cloned().collect(),
+            |_d: &mut PulseState, _x: im::Vector<Card>| (),
+        )),
+        1.0,
+    );
+
+    lists.add_flex_child(
+        Scroll::new(List::new(build_client_group))
+        .vertical()
+        .lens(lens::Id.map(
+            client_groups,
+            |d: &mut PulseState, x: im::Vector<ClientGroup>| {
+                for group in x {
+                    for row in group.sinkinputs {
+                        d.sinkinputs.insert(SinkInputId(row.sink_input.index), row.sink_input);
+                    }
+                    for sourceoutput in group.sourceoutputs {
+                        d.sourceoutputs.insert(SourceOutputId(sourceoutput.index), sourceoutput);
+                    }
+                }
+            },
+        )),
+        1.0,
+    );
+
+    let load_module = Flex::row()
+        .with_flex_child(TextBox::new().lens(PulseState::module_name).expand_width(), 1.0)
+        .with_flex_child(TextBox::new().lens(PulseState::module_arguments).expand_width(), 2.0)
+        .with_child(Button::new("Load").on_click(|ctx, d: &mut PulseState, env| {
+            let name = d.module_name.trim();
+            if name.is_empty() {
+                return;
+            }
+            let module = RawModule { name: name.to_string(), arguments: d.module_arguments.clone() };
+            ctx.submit_command(PULSE_COMMAND.with((server_of(env), PulseCommand::LoadModule(module))), None);
+        }))
+        .with_child(Button::new("New virtual sink").on_click(|ctx, _d: &mut PulseState, env| {
+            let command = PulseCommand::load_module(&NullSink::new().description("Virtual sink"));
+            ctx.submit_command(PULSE_COMMAND.with((server_of(env), command)), None);
+        }))
+        .padding(5.0);
+    let modules = Scroll::new(List::new(|| {
+        Flex::column()
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .with_child(
+                Flex::row()
+                    .with_child(Label::new(|item: &Module, _env: &_| {
+                        format!("#{} {}", item.index, display_name(&[&item.name]))
+                    }))
+                    .with_spacer(10.0)
+                    .with_child(Button::new("Unload").on_click(|ctx, item: &mut Module, env| {
+                        let command = PulseCommand::UnloadModule(ModuleId(item.index));
+                        ctx.submit_command(PULSE_COMMAND.with((server_of(env), command)), None);
+                    })),
+            )
+            .with_child(Label::new(|item: &Module, _env: &_| item.argument.clone()))
+            .with_child(Label::new(|item: &Module, _env: &_| {
+                match item.n_used {
+                    Some(n) => format!("Used by {}", n),
+                    None => "Usage unknown".to_string(),
+                }
+            }))
+            .padding(10.0)
+            .background(Color::rgb(0.3, 0.3, 0.3))
+    }))
+        .vertical()
+        .lens(lens::Id.map(
+            |d: &PulseState| {
+                let mut modules: Vec<_> = d.modules.values().cloned().collect();
+                modules.sort_by_key(|m| m.index);
+                modules.into_iter().collect()
+            },
+            |_d: &mut PulseState, _x: im::Vector<Module>| (),
+        ));
+    lists.add_flex_child(
+        Flex::column()
+            .with_child(load_module)
+            .with_flex_child(modules, 1.0),
+        1.0,
+    );
+
+    root.add_flex_child(lists, 1.0);
+    root.add_child(Label::new(|d: &PulseState, _env: &_| d.status.clone()).padding(5.0));
+    root.controller(PulseCommunication::new(server, pulse))
+}
+
+// The first name that isn't empty.
+fn display_name<'a>(names: &[&'a String]) -> &'a str {
+    names.iter().find(|name| !name.is_empty()).map(|name| name.as_str()).unwrap_or("No Name :-(")
+}
+
+fn sink_rows(d: &PulseState) -> im::Vector<SinkRow> {
+    let default = d.server.as_ref().map(|s| &s.default_sink);
+    let mut sinks: Vec<_> = d.sinks.values().cloned().collect();
+    sinks.sort_by_key(|s| s.index);
+    sinks.into_iter()
+        .map(|sink| SinkRow {
+            default: default == Some(&sink.name),
+            peak: d.peak(&ObjectRef::Sink(SinkId(sink.index))),
+            sink,
+        })
+        .collect()
+}
+
+fn source_rows(d: &PulseState) -> im::Vector<SourceRow> {
+    let default = d.server.as_ref().map(|s| &s.default_source);
+    let mut sources: Vec<_> = d.sources.values().cloned().collect();
+    sources.sort_by_key(|s| s.index);
+    sources.into_iter()
+        .map(|source| SourceRow {
+            default: default == Some(&source.name),
+            peak: d.peak(&ObjectRef::Source(SourceId(source.index))),
+            source,
+        })
+        .collect()
+}
+
+#[derive(Clone, Lens, Data, Debug)]
+struct SinkRow {
+    sink: Sink,
+    default: bool,
+    peak: f64,
+}
+
+#[derive(Clone, Lens, Data, Debug)]
+struct SourceRow {
+    source: Source,
+    default: bool,
+    peak: f64,
+}
+
+#[derive(Clone, Lens, Data, Debug)]
+struct SinkInputRow {
+    sink_input: SinkInput,
+    peak: f64,
+}
+
+/// Rows that map to a pulse object with a volume and a mute switch.
+trait ObjectRow {
+    fn target(&self) -> ObjectRef;
+    fn volume(&self) -> &Vec<u32>;
+    fn volume_mut(&mut self) -> &mut Vec<u32>;
+    fn mute(&self) -> bool;
+    fn mute_mut(&mut self) -> &mut bool;
+    fn ports(&self) -> im::Vector<PortRow> {
+        im::Vector::new()
+    }
+    fn peak(&self) -> f64 {
+        0.0
+    }
+}
+
+impl ObjectRow for SinkRow {
+    fn target(&self) -> ObjectRef { ObjectRef::Sink(SinkId(self.sink.index)) }
+    fn volume(&self) -> &Vec<u32> { &self.sink.volume }
+    fn volume_mut(&mut self) -> &mut Vec<u32> { &mut self.sink.volume }
+    fn mute(&self) -> bool { self.sink.mute }
+    fn mute_mut(&mut self) -> &mut bool { &mut self.sink.mute }
+    fn peak(&self) -> f64 { self.peak }
+    fn ports(&self) -> im::Vector<PortRow> {
+        self.sink.ports.iter()
+            .map(|p| PortRow::new(self.target(), p, self.sink.active_port.as_ref()))
+            .collect()
+    }
+}
+
+impl ObjectRow for SourceRow {
+    fn target(&self) -> ObjectRef { ObjectRef::Source(SourceId(self.source.index)) }
+    fn volume(&self) -> &Vec<u32> { &self.source.volume }
+    fn volume_mut(&mut self) -> &mut Vec<u32> { &mut self.source.volume }
+    fn mute(&self) -> bool { self.source.mute }
+    fn mute_mut(&mut self) -> &mut bool { &mut self.source.mute }
+    fn peak(&self) -> f64 { self.peak }
+    fn ports(&self) -> im::Vector<PortRow> {
+        self.source.ports.iter()
+            .map(|p| PortRow::new(self.target(), p, self.source.active_port.as_ref()))
+            .collect()
+    }
+}
+
+impl ObjectRow for SinkInputRow {
+    fn target(&self) -> ObjectRef { ObjectRef::SinkInput(SinkInputId(self.sink_input.index)) }
+    fn volume(&self) -> &Vec<u32> { &self.sink_input.volume }
+    fn volume_mut(&mut self) -> &mut Vec<u32> { &mut self.sink_input.volume }
+    fn mute(&self) -> bool { self.sink_input.mute }
+    fn mute_mut(&mut self) -> &mut bool { &mut self.sink_input.mute }
+    fn peak(&self) -> f64 { self.peak }
+}
+
+impl ObjectRow for SourceOutput {
+    fn target(&self) -> ObjectRef { ObjectRef::SourceOutput(SourceOutputId(self.index)) }
+    fn volume(&self) -> &Vec<u32> { &self.volume }
+    fn volume_mut(&mut self) -> &mut Vec<u32> { &mut self.volume }
+    fn mute(&self) -> bool { self.mute }
+    fn mute_mut(&mut self) -> &mut bool { &mut self.mute }
+}
+
+// Up to 150%, like pavucontrol.
+const MAX_VOLUME: f64 = 1.5;
+
+fn volume_slider<T: ObjectRow + Data>() -> impl Widget<T> {
+    Slider::new().with_range(0.0, MAX_VOLUME).lens(VolumeLens).fix_width(150.0)
+}
+
+#[derive(Clone, Data, Lens, Debug)]
+struct PortRow {
+    #[data(same_fn="PartialEq::eq")]
+    device: ObjectRef,
+    name: String,
+    description: String,
+    priority: u32,
+    // None if pulse doesn't know
+    available: Option<bool>,
+    active: bool,
+}
+
+impl PortRow {
+    fn new(device: ObjectRef, port: &Port, active: Option<&String>) -> Self {
+        PortRow {
+            device,
+            description: display_name(&[&port.description, &port.name]).to_string(),
+            active: active == Some(&port.name),
+            name: port.name.clone(),
+            priority: port.priority,
+            available: port.available,
+        }
+    }
+
+    fn label(&self) -> String {
+        let available = match self.available {
+            Some(true) => "available",
+            Some(false) => "unplugged",
+            None => "availability unknown",
+        };
+        format!("{} ({}, priority {})", self.description, available, self.priority)
+    }
+}
+
+fn port_dropdown<T: ObjectRow + Data>() -> impl Widget<T> {
+    let header = Label::new(|item: &T, _env: &_| {
+        let ports = item.ports();
+        match ports.iter().find(|p| p.active) {
+            Some(port) => format!("Port: {} ▾", port.label()),
+            None if ports.is_empty() => String::new(),
+            None => "Port: none ▾".to_string(),
+        }
+    });
+    let menu = List::new(|| {
+        Button::new(|port: &PortRow, _env: &_| {
+            if port.active {
+                format!("• {}", port.label())
+            } else {
+                port.label()
+            }
+        })
+        .on_click(|ctx, port: &mut PortRow, env| {
+            let command = PulseCommand::SetPort(port.device.clone(), port.name.clone());
+            ctx.submit_command(PULSE_COMMAND.with((server_of(env), command)), None);
+        })
+    })
+    .lens(lens::Id.map(
+        |d: &T| d.ports(),
+        |_d: &mut T, _x: im::Vector<PortRow>| (),
+    ));
+    Dropdown::new(header, menu)
+}
+
+#[derive(Clone, Data, Lens, Debug)]
+struct ProfileRow {
+    #[data(same_fn="PartialEq::eq")]
+    card: CardId,
+    name: String,
+    description: String,
+    available: bool,
+    active: bool,
+}
+
+impl ProfileRow {
+    fn label(&self) -> String {
+        if self.available {
+            self.description.clone()
+        } else {
+            format!("{} (unavailable)", self.description)
+        }
+    }
+}
+
+fn profile_rows(card: &Card) -> im::Vector<ProfileRow> {
+    let active = card.active_profile.as_ref().map(|p| &p.name);
+    card.profiles.iter()
+        .map(|profile| ProfileRow {
+            card: CardId(card.index),
+            name: profile.name.clone(),
+            description: display_name(&[&profile.description, &profile.name]).to_string(),
+            available: profile.available,
+            active: active == Some(&profile.name),
+        })
+        .collect()
+}
+
+fn profile_dropdown() -> impl Widget<Card> {
+    let header = Label::new(|item: &Card, _env: &_| {
+        let profile = item.active_profile.as_ref()
+            .map(|p| display_name(&[&p.description, &p.name]))
+            .unwrap_or("off");
+        format!("{}: {} ▾", display_name(&[&item.name]), profile)
+    });
+    let menu = List::new(|| {
+        Button::new(|profile: &ProfileRow, _env: &_| {
+            if profile.active {
+                format!("• {}", profile.label())
+            } else {
+                profile.label()
+            }
+        })
+        .on_click(|ctx, profile: &mut ProfileRow, env| {
+            let command = PulseCommand::SetCardProfile(profile.card, profile.name.clone());
+            ctx.submit_command(PULSE_COMMAND.with((server_of(env), command)), None);
+        })
+    })
+    .lens(lens::Id.map(
+        profile_rows,
+        |_d: &mut Card, _x: im::Vector<ProfileRow>| (),
+    ));
+    Dropdown::new(header, menu)
+}
+
+// Druid has no drag and drop, so we fake it: pressing a stream's label remembers the stream in
+// PulseCommunication, releasing the mouse over a device row moves the stream there.
+
+struct DragHandle;
+
+impl<T: ObjectRow + Data, W: Widget<T>> Controller<T, W> for DragHandle {
+    fn event(&mut self, child: &mut W, ctx: &mut druid::EventCtx<'_, '_>, event: &druid::Event, data: &mut T, env: &druid::Env) {
+        if let druid::Event::MouseDown(_) = event {
+            ctx.submit_command(DRAG_START.with((server_of(env), data.target())), None);
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+struct DropTarget;
+
+impl<T: ObjectRow + Data, W: Widget<T>> Controller<T, W> for DropTarget {
+    fn event(&mut self, child: &mut W, ctx: &mut druid::EventCtx<'_, '_>, event: &druid::Event, data: &mut T, env: &druid::Env) {
+        if let druid::Event::MouseUp(_) = event {
+            ctx.submit_command(DROP.with((server_of(env), data.target())), None);
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+fn peak_meter<T: ObjectRow + Data>() -> impl Widget<T> {
+    ProgressBar::new()
+        .lens(lens::Id.map(
+            |d: &T| d.peak(),
+            |_d: &mut T, _x: f64| (),
+        ))
+        .fix_width(100.0)
+}
+
+fn mute_toggle<T: ObjectRow + Data>() -> impl Widget<T> {
+    Checkbox::new("Mute").lens(lens::Id.map(
+        |d: &T| d.mute(),
+        |d: &mut T, mute: bool| *d.mute_mut() = mute,
+    ))
+}
+
+/// The volume of the loudest channel, 1.0 being 100%. Setting it keeps the balance between channels.
+struct VolumeLens;
+
+impl<T: ObjectRow> Lens<T, f64> for VolumeLens {
+    fn with<V, F: FnOnce(&f64) -> V>(&self, data: &T, f: F) -> V {
+        f(&volume_fraction(&channel_volumes(data.volume())))
+    }
+
+    fn with_mut<V, F: FnOnce(&mut f64) -> V>(&self, data: &mut T, f: F) -> V {
+        let mut volumes = channel_volumes(data.volume());
+        let before = volume_fraction(&volumes);
+        let mut fraction = before;
+        let result = f(&mut fraction);
+        if fraction != before {
+            set_volume_fraction(&mut volumes, fraction);
+            *data.volume_mut() = levels(&volumes);
+        }
+        result
+    }
+}
+
+/// Turns edits of a row into commands for the pulse thread.
+struct RowCommands;
+
+impl<T: ObjectRow + Data, W: Widget<T>> Controller<T, W> for RowCommands {
+    fn event(&mut self, child: &mut W, ctx: &mut druid::EventCtx<'_, '_>, event: &druid::Event, data: &mut T, env: &druid::Env) {
+        let volume = data.volume().clone();
+        let mute = data.mute();
+        child.event(ctx, event, data, env);
+        if data.volume() != &volume {
+            let command = PulseCommand::SetVolume(data.target(), channel_volumes(data.volume()));
+            ctx.submit_command(PULSE_COMMAND.with((server_of(env), command)), None);
+        }
+        if data.mute() != mute {
+            let command = PulseCommand::SetMute(data.target(), data.mute());
+            ctx.submit_command(PULSE_COMMAND.with((server_of(env), command)), None);
+        }
+    }
+}
+
+fn build_client_group() -> impl Widget<ClientGroup> {
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new(|item: &ClientGroup, _env: &_| {
+            item.client.as_ref().map(client_label).unwrap_or_else(|| "No Client".to_string())
+        }))
+        .with_child(
+            List::new(|| {
+                Flex::row()
+                    .with_child(Label::new(|item: &SinkInputRow, _env: &_| {
+                        format!("Playback: {}", display_name(&[&item.sink_input.name]))
+                    }).controller(DragHandle))
+                    .with_flex_spacer(1.0)
+                    .with_child(peak_meter())
+                    .with_child(volume_slider())
+                    .with_child(mute_toggle())
+                    .controller(RowCommands)
+            })
+            .lens(ClientGroup::sinkinputs),
+        )
+        .with_child(
+            List::new(|| {
+                Flex::row()
+                    .with_child(Label::new(|item: &SourceOutput, _env: &_| {
+                        format!("Recording: {}", display_name(&[&item.name]))
+                    }).controller(DragHandle))
+                    .with_flex_spacer(1.0)
+                    .with_child(volume_slider())
+                    .with_child(mute_toggle())
+                    .controller(RowCommands)
+            })
+            .lens(ClientGroup::sourceoutputs),
+        )
+        .padding(10.0)
+        .background(Color::rgb(0.0, 0.4, 0.2))
+}
+
+fn client_label(client: &Client) -> String {
+    let name = match client.properties.get(properties::APPLICATION_NAME) {
+        Some(name) => name.clone(),
+        None => display_name(&[&client.name]).to_string(),
+    };
+    match client.properties.get(properties::APPLICATION_PROCESS_BINARY) {
+        Some(binary) => format!("{} ({})", name, binary),
+        None => name,
+    }
+}
+
+/// Sink inputs and source outputs grouped by the client owning them.
+/// Streams without a (known) client end up in the `None` group.
+fn client_groups(d: &PulseState) -> im::Vector<ClientGroup> {
+    let mut groups: BTreeMap<Option<ClientId>, ClientGroup> = d.clients.iter()
+        .map(|(id, client)| (Some(*id), ClientGroup::new(Some(client.clone()))))
+        .collect();
+    let group_of = |client: Option<u32>| {
+        client.map(ClientId).filter(|id| d.clients.contains_key(id))
+    };
+    for sinkinput in d.sinkinputs.values() {
+        groups.entry(group_of(sinkinput.client))
+            .or_insert_with(|| ClientGroup::new(None))
+            .sinkinputs.push_back(SinkInputRow {
+                sink_input: sinkinput.clone(),
+                peak: d.peak(&ObjectRef::SinkInput(SinkInputId(sinkinput.index))),
+            });
+    }
+    for sourceoutput in d.sourceoutputs.values() {
+        groups.entry(group_of(sourceoutput.client))
+            .or_insert_with(|| ClientGroup::new(None))
+            .sourceoutputs.push_back(sourceoutput.clone());
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+#[derive(Clone, Lens, Data, Debug)]
+struct ClientGroup {
+    client: Option<Client>,
+    sinkinputs: im::Vector<SinkInputRow>,
+    sourceoutputs: im::Vector<SourceOutput>,
+}
+
+impl ClientGroup {
+    fn new(client: Option<Client>) -> Self {
+        ClientGroup {
+            client,
+            sinkinputs: im::Vector::new(),
+            sourceoutputs: im::Vector::new(),
+        }
+    }
+}
+
+#[derive(Clone, Lens, Data, Debug)]
+// #[data(same_fn="PartialEq::eq")]
+struct PulseState {
+    #[data(same_fn="PartialEq::eq")]
+    id: ServerId,
+    name: String,
+    sinks: im::HashMap<SinkId, Sink>,
+    sources: im::HashMap<SourceId, Source>,
+    sourceoutputs: im::HashMap<SourceOutputId, SourceOutput>,
+    sinkinputs: im::HashMap<SinkInputId, SinkInput>,
+    cards: im::HashMap<CardId, Card>,
+    clients: im::HashMap<ClientId, Client>,
+    modules: im::HashMap<ModuleId, Module>,
+    server: Option<Server>,
+    // What the load module row is filled with.
+    module_name: String,
+    module_arguments: String,
+    status: String,
+    peaks: im::HashMap<ObjectRef, f64>,
+}
+
+impl PulseState {
+    fn new(id: ServerId, name: String) -> Self {
+        PulseState {
+            id,
+            name,
+            sinks: im::HashMap::new(),
+            sources: im::HashMap::new(),
+            sourceoutputs: im::HashMap::new(),
+            sinkinputs: im::HashMap::new(),
+            cards: im::HashMap::new(),
+            clients: im::HashMap::new(),
+            modules: im::HashMap::new(),
+            server: None,
+            module_name: String::new(),
+            module_arguments: String::new(),
+            status: String::new(),
+            peaks: im::HashMap::new(),
+        }
+    }
+
+    fn peak(&self, target: &ObjectRef) -> f64 {
+        self.peaks.get(target).cloned().unwrap_or(0.0)
+    }
+
+    /// The meters we want for what is currently shown.
+    fn peak_sources(&self) -> HashSet<PeakSource> {
+        let sinks = self.sinks.iter().map(|(id, sink)| PeakSource::Sink {
+            sink: *id,
+            monitor_source: SourceId(sink.monitor_source),
+        });
+        let sources = self.sources.keys().map(|id| PeakSource::Source(*id));
+        let sinkinputs = self.sinkinputs.iter().filter_map(|(id, sinkinput)| {
+            self.sinks.get(&SinkId(sinkinput.sink)).map(|sink| PeakSource::SinkInput {
+                sink_input: *id,
+                monitor_source: SourceId(sink.monitor_source),
+            })
+        });
+        sinks.chain(sources).chain(sinkinputs).collect()
+    }
+}
+
+struct PulseCommunication {
+    server: ServerId,
+    pulse: PulseHandle,
+    dragging: Option<ObjectRef>,
+    meters: HashSet<PeakSource>,
+}
+
+impl PulseCommunication {
+    fn new(server: ServerId, pulse: PulseHandle) -> Self {
+        PulseCommunication { server, pulse, dragging: None, meters: HashSet::new() }
+    }
+
+    /// Starts and stops meters so that there is one for every row.
+    fn update_meters(&mut self, data: &mut PulseState) {
+        let wanted = data.peak_sources();
+        for source in self.meters.difference(&wanted) {
+            data.peaks.remove(&source.target());
+            self.send(data, PulseCommand::StopPeakMeter(*source));
+        }
+        for source in wanted.difference(&self.meters) {
+            self.send(data, PulseCommand::StartPeakMeter(*source));
+        }
+        self.meters = wanted;
+    }
+
+    fn send(&self, data: &mut PulseState, command: PulseCommand) {
+        if let Err(command) = self.pulse.send(command) {
+            data.status = format!("Pulse connection is gone: {:?}", command);
+        }
+    }
+}
+
+impl<W: Widget<PulseState>> Controller<PulseState, W> for PulseCommunication {
+    fn event(&mut self, child: &mut W, ctx: &mut druid::EventCtx<'_, '_>, event: &druid::Event, data: &mut PulseState, env: &druid::Env) {
+        match event {
+            druid::Event::Command(cmd) if cmd.is(PULSE_COMMAND) => {
+                let (server, command) = cmd.get_unchecked(PULSE_COMMAND);
+                if *server == self.server {
+                    self.send(data, command.clone());
+                }
+            }
+            druid::Event::Command(cmd) if cmd.is(DRAG_START) => {
+                let (server, target) = cmd.get_unchecked(DRAG_START);
+                // Streams can't be moved between servers, a drag elsewhere ends ours.
+                self.dragging = Some(target.clone()).filter(|_| *server == self.server);
+            }
+            druid::Event::Command(cmd) if cmd.is(DROP) => {
+                let (server, target) = cmd.get_unchecked(DROP);
+                if *server != self.server {
+                    self.dragging = None;
+                    return;
+                }
+                match (self.dragging.take(), target) {
+                    (Some(ObjectRef::SinkInput(sink_input)), ObjectRef::Sink(sink)) => {
+                        self.send(data, PulseCommand::MoveSinkInput(sink_input, *sink));
+                    }
+                    (Some(ObjectRef::SourceOutput(source_output)), ObjectRef::Source(source)) => {
+                        self.send(data, PulseCommand::MoveSourceOutput(source_output, *source));
+                    }
+                    _ => (),
+                }
+            }
+            druid::Event::MouseDown(_) => {
+                // A new press starts a new drag, if it lands on a handle.
+                self.dragging = None;
+                child.event(ctx, event, data, env)
+            }
+            druid::Event::Command(cmd) if cmd.is(PULSE_CHANGES) => {
+                let (server, message) = cmd.get_unchecked(PULSE_CHANGES);
+                if *server != self.server {
+                    return;
+                }
+                if let PulseMessage::MsgPeaks{peaks} = message {
+                    for (source, peak) in peaks {
+                        if self.meters.contains(source) {
+                            data.peaks.insert(source.target(), *peak as f64);
+                        }
+                    }
+                    return;
+                }
+                match message.clone() {
+                    PulseMessage::MsgAdd{id, msg} => {
+                        match (id, msg) {
+                            (PulseId::Sink(id), MsgSink(s)) => {data.sinks.insert(id, Sink::from(&s));},
+                            (PulseId::Source(id), MsgSource(s)) => {data.sources.insert(id, Source::from(&s));},
+                            (PulseId::SinkInput(id), MsgSinkInput(s)) => {data.sinkinputs.insert(id, SinkInput::from(&s));},
+                            (PulseId::SourceOutput(id), MsgSourceOutput(s)) => {data.sourceoutputs.insert(id, SourceOutput::from(&s));},
+                            (PulseId::Card(id), MsgCard(s)) => {data.cards.insert(id, Card::from(&s));},
+                            (PulseId::Client(id), MsgClient(s)) => {data.clients.insert(id, Client::from(&s));},
+                            (PulseId::Module(id), MsgModule(s)) => {data.modules.insert(id, Module::from(&s));},
+                            // The id is taken from the message, they always match.
+                            _ => (),
+                        }
+                    },
+                    PulseMessage::MsgDel{id} => {
+                        match id {
+                            PulseId::Sink(id) => {data.sinks.remove(&id);},
+                            PulseId::Source(id) => {data.sources.remove(&id);},
+                            PulseId::SinkInput(id) => {data.sinkinputs.remove(&id);},
+                            PulseId::SourceOutput(id) => {data.sourceoutputs.remove(&id);},
+                            PulseId::Card(id) => {data.cards.remove(&id);},
+                            PulseId::Client(id) => {data.clients.remove(&id);},
+                            PulseId::Module(id) => {data.modules.remove(&id);},
+                            _ => (),
+                        }
+                    }
+                    PulseMessage::MsgServer{info} => {
+                        data.server = Some(Server::from(&info));
+                    }
+                    PulseMessage::MsgCommandResult{command: PulseCommand::StartPeakMeter(_), success: true}
+                    | PulseMessage::MsgCommandResult{command: PulseCommand::StopPeakMeter(_), success: true} => (),
+                    PulseMessage::MsgCommandResult{command, success} => {
+                        data.status = if success {
+                            format!("Done: {:?}", command)
+                        } else {
+                            format!("Failed: {:?}", command)
+                        };
+                    }
+                    PulseMessage::MsgPeaks{..} => (),
+                    PulseMessage::MsgError{error} => {
+                        data.status = format!("Pulse error: {}", error);
+                    }
+                    PulseMessage::MsgConnection{state: ConnectionState::Connecting} => {
+                        data.status = "Connecting to pulse...".to_string();
+                    }
+                    PulseMessage::MsgConnection{state: ConnectionState::Ready} => {
+                        data.status = "Connected, loading...".to_string();
+                    }
+                    PulseMessage::MsgSyncComplete => {
+                        data.status = "Connected".to_string();
+                    }
+                    PulseMessage::MsgConnection{state: ConnectionState::Disconnected{error, retry_in}} => {
+                        // The server forgot about our meters as well, the next sync starts them again.
+                        self.meters.clear();
+                        self.dragging = None;
+                        *data = PulseState::new(data.id, data.name.clone());
+                        data.status = match error {
+                            Some(error) => format!("Disconnected: {}, retrying in {:?}", error, retry_in),
+                            None => format!("Disconnected, retrying in {:?}", retry_in),
+                        };
+                    }
+                }
+                self.update_meters(data);
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
\ No newline at end of file
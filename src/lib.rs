@@ -0,0 +1,28 @@
+//! An async wrapper around libpulse. Start a connection with `PulseHandle::spawn`, read what
+//! happens on the server from the returned stream of `PulseMessage`s and send `PulseCommand`s
+//! through the handle.
+//!
+//! The GUI lives in the binary. The library itself only touches druid to derive `druid::Data` on
+//! the model types, and only with the `gui` feature. That feature is on by default, as is `bin`
+//! pulling in tokio for the binaries, so tools that don't want druid, GTK or tokio should depend
+//! on this crate with `default-features = false`.
+
+extern crate libpulse_binding as pulse;
+extern crate libpulse_sys as pulse_sys;
+
+mod futuristic_pulse;
+
+pub use crate::futuristic_pulse::commands::{CommandOutput, PulseCommand};
+pub use crate::futuristic_pulse::error::PulseError;
+pub use crate::futuristic_pulse::handle::{ListKind, ListReply, PulseHandle};
+pub use crate::futuristic_pulse::ids::{
+    CardId, ClientId, ModuleId, ObjectRef, PulseId, SampleId, ServerId, SinkId, SinkInputId, SourceId,
+    SourceOutputId,
+};
+pub use crate::futuristic_pulse::model::{
+    channel_volumes, facility_tag, levels, set_volume_fraction, volume_fraction, Card, CardProfile, Client, Event,
+    Module, Port, Server, Sink, SinkInput, Source, SourceOutput,
+};
+pub use crate::futuristic_pulse::modules::{CombineSink, Loopback, ModuleArguments, ModuleSpec, NullSink, RawModule};
+pub use crate::futuristic_pulse::peaks::PeakSource;
+pub use crate::futuristic_pulse::{ConnectionState, PulseAddMessage, PulseMessage};